@@ -7,89 +7,12 @@ use {passport_for_ai::local, std::path::Path};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    // Initialize the logger
-    tracing_subscriber::fmt::init();
-
-    let matches = Command::new("ai-passport")
-        .version("0.1")
-        .about("AI Passport CLI")
-        .subcommand(
-            Command::new("local")
-                .about("Operations for local models")
-                .subcommand(
-                    Command::new("create-passport")
-                        .about("Creates a unique passport for a local ONNX model")
-                        .arg(
-                            Arg::new("model_path")
-                                .help("Path to the ONNX model file")
-                                .required(true)
-                                .index(1),
-                        )
-                        .arg(
-                            Arg::new("save_to_path")
-                                .long("save-to")
-                                .help("Optional path to the directory where the passport will be saved")
-                                .value_hint(clap::ValueHint::DirPath),
-                        ),
-                )
-                .subcommand(
-                    Command::new("attribute-content")
-                        .about("Attributes content to the local model")
-                        .arg(
-                            Arg::new("model_path")
-                                .help("Path to the model to attribute the content to")
-                                .required(true)
-                                .index(1),
-                        )
-                        .arg(
-                            Arg::new("content_path")
-                                .help("Path to the content file to attribute")
-                                .required(true)
-                                .index(2),
-                        )
-                        .arg(
-                            Arg::new("save_to_path")
-                                .long("save-to")
-                                .help("Optional path to the directory where the proof will be saved")
-                                .value_hint(clap::ValueHint::DirPath),
-                        ),
-                )
-                .subcommand(
-                    Command::new("verify-attribution")
-                        .about("Verifies the cryptographic proof for a local ONNX model")
-                        .arg(
-                            Arg::new("model_passport_path")
-                                .help("Path to the JSON model passport")
-                                .required(true)
-                                .index(1),
-                        )
-                        .arg(
-                            Arg::new("attribution_certificate_path")
-                                .help("Path to the attribution certificate file (attribution_certificate.json)")
-                                .required(true)
-                                .index(2),
-                        ),
-                ),
-        )
-        .subcommand(
-            Command::new("remote")
-                .about("Operations for remote models")
-                .subcommand(
-                    Command::new("attributed-conversation")
-                        .about("Interact with any model API to generate an attribution proof of conversation"),
-                )
-                .subcommand(
-                    Command::new("verify-attribution")
-                        .about("Verifies the cryptographic proof for a remote model")
-                        .arg(
-                            Arg::new("proof_path")
-                                .help("Path to the JSON proof file")
-                                .required(true)
-                                .index(1),
-                        ),
-                ),
-        )
-        .get_matches();
+    // Initialize the logger, keeping stdout free for command output
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    let matches = cli().get_matches();
 
     #[allow(unused_variables)]
     // Handle `local` commands
@@ -141,16 +64,41 @@ async fn main() -> Result<(), Box<dyn Error>> {
     else if let Some(remote_matches) = matches.subcommand_matches("remote") {
         #[cfg(feature = "remote")]
         {
-            if remote_matches
-                .subcommand_matches("attributed-conversation")
-                .is_some()
-            {
-                remote::generate_conversation_attribution()
+            if let Some(matches) = remote_matches.subcommand_matches("attributed-conversation") {
+                let file = match matches.get_one::<std::path::PathBuf>("config") {
+                    Some(path) => remote::ConfigFile::load(path)
+                        .map_err(|err| format!("Error loading the config file: {:#}", err))?,
+                    None => remote::ConfigFile::default(),
+                };
+
+                let options = conversation_options(matches, file)?;
+
+                remote::generate_conversation_attribution(options)
                     .await
                     .map_err(|err| format!("Error during conversation: {}", err))?;
+            } else if let Some(matches) = remote_matches.subcommand_matches("verify-manifest") {
+                let manifest_path = matches
+                    .get_one::<std::path::PathBuf>("manifest_path")
+                    .unwrap();
+                let manifest = remote::verify_manifest(manifest_path)
+                    .map_err(|err| format!("Error verifying the manifest: {}", err))?;
+                println!(
+                    "✅ All {} file(s) of run `{}` are present and unmodified.",
+                    manifest.files.len(),
+                    manifest.run_id
+                );
+            } else if remote_matches.subcommand_matches("list-models").is_some() {
+                remote::list_models()
+                    .await
+                    .map_err(|err| format!("Error listing models: {}", err))?;
             } else if let Some(matches) = remote_matches.subcommand_matches("verify-attribution") {
                 let proof_path = matches.get_one::<String>("proof_path").unwrap();
-                remote::verify_attribution(proof_path)
+                let notary_pubkey_paths = matches
+                    .get_many::<std::path::PathBuf>("notary_pubkey")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                remote::verify_attribution(proof_path, &notary_pubkey_paths)
                     .map_err(|err| format!("Error verifying attribution: {}", err))?;
             } else {
                 eprintln!("Error: The specified remote feature is not available yet. Currently, only 'anthropic-conversation' is supported.");
@@ -169,3 +117,585 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+/// The command line interface of the binary
+fn cli() -> Command {
+    Command::new("ai-passport")
+    .version("0.1")
+    .about("AI Passport CLI")
+    .subcommand(
+        Command::new("local")
+            .about("Operations for local models")
+            .subcommand(
+                Command::new("create-passport")
+                    .about("Creates a unique passport for a local ONNX model")
+                    .arg(
+                        Arg::new("model_path")
+                            .help("Path to the ONNX model file")
+                            .required(true)
+                            .index(1),
+                    )
+                    .arg(
+                        Arg::new("save_to_path")
+                            .long("save-to")
+                            .help("Optional path to the directory where the passport will be saved")
+                            .value_hint(clap::ValueHint::DirPath),
+                    ),
+            )
+            .subcommand(
+                Command::new("attribute-content")
+                    .about("Attributes content to the local model")
+                    .arg(
+                        Arg::new("model_path")
+                            .help("Path to the model to attribute the content to")
+                            .required(true)
+                            .index(1),
+                    )
+                    .arg(
+                        Arg::new("content_path")
+                            .help("Path to the content file to attribute")
+                            .required(true)
+                            .index(2),
+                    )
+                    .arg(
+                        Arg::new("save_to_path")
+                            .long("save-to")
+                            .help("Optional path to the directory where the proof will be saved")
+                            .value_hint(clap::ValueHint::DirPath),
+                    ),
+            )
+            .subcommand(
+                Command::new("verify-attribution")
+                    .about("Verifies the cryptographic proof for a local ONNX model")
+                    .arg(
+                        Arg::new("model_passport_path")
+                            .help("Path to the JSON model passport")
+                            .required(true)
+                            .index(1),
+                    )
+                    .arg(
+                        Arg::new("attribution_certificate_path")
+                            .help("Path to the attribution certificate file (attribution_certificate.json)")
+                            .required(true)
+                            .index(2),
+                    ),
+            ),
+    )
+    .subcommand(
+        Command::new("remote")
+            .about("Operations for remote models")
+            .subcommand(
+                Command::new("attributed-conversation")
+                    .about("Interact with any model API to generate an attribution proof of conversation")
+                    .arg(
+                        Arg::new("config")
+                            .long("config")
+                            .help("Path to a TOML file with conversation options named after their flags. Flags given on the command line take precedence, e.g. `--verify-on-save=false` turns off an option enabled in the file")
+                            .value_hint(clap::ValueHint::FilePath)
+                            .value_parser(clap::value_parser!(std::path::PathBuf)),
+                    )
+                    .arg(
+                        Arg::new("stdout")
+                            .long("stdout")
+                            .help("Print the proof to stdout instead of saving it to a file")
+                            .action(clap::ArgAction::SetTrue),
+                    )
+                    .arg(
+                        Arg::new("completion_style")
+                            .long("completion-style")
+                            .help("Use the `chat` completions endpoint or the legacy `text` completions endpoint")
+                            .value_parser(["chat", "text"])
+                            .default_value("chat"),
+                    )
+                    .arg(
+                        Arg::new("reveal")
+                            .long("reveal")
+                            .help("Which side of the conversation to reveal in the proof; the other side stays committed but hidden")
+                            .value_parser(["both", "response", "request"])
+                            .default_value("both"),
+                    )
+                    .arg(
+                        Arg::new("header")
+                            .long("header")
+                            .help("Additional `Name: Value` header to send with every request to the model API (e.g. a User-Agent), can be repeated")
+                            .value_parser(parse_header)
+                            .action(clap::ArgAction::Append),
+                    )
+                    .arg(
+                        Arg::new("model")
+                            .long("model")
+                            .help("Model ID to interact with, takes precedence over the `MODEL_ID` environment variable and skips the interactive selection"),
+                    )
+                    .arg(
+                        Arg::new("print_config")
+                            .long("print-config")
+                            .help("Print the resolved configuration and where each value came from before starting")
+                            .action(clap::ArgAction::SetTrue),
+                    )
+                    .arg(
+                        Arg::new("expires_in")
+                            .long("expires-in")
+                            .help("Number of seconds the proof remains valid for, verifiers reject the proof afterwards")
+                            .value_parser(clap::value_parser!(u64)),
+                    )
+                    .arg(
+                        Arg::new("dump_transcript")
+                            .long("dump-transcript")
+                            .help("Developer option: write the raw sent and received transcripts to this directory. WARNING: they are not censored and contain secrets")
+                            .value_hint(clap::ValueHint::DirPath)
+                            .value_parser(clap::value_parser!(std::path::PathBuf)),
+                    )
+                    .arg(
+                        Arg::new("message")
+                            .long("message")
+                            .help("Attest a single message to the model without interactive input, the conversation ends after the reply"),
+                    )
+                    .arg(
+                        Arg::new("prompts_file")
+                            .long("prompts-file")
+                            .help("Attest each line of this file as a separate single message, producing one numbered proof per prompt")
+                            .value_hint(clap::ValueHint::FilePath)
+                            .value_parser(clap::value_parser!(std::path::PathBuf))
+                            .conflicts_with_all(["message", "stdout"]),
+                    )
+                    .arg(
+                        Arg::new("connect_timeout")
+                            .long("connect-timeout")
+                            .help("Number of seconds allowed to connect to the model API, including the TLS handshake, which completes with the first response [default: 60]")
+                            .value_parser(clap::value_parser!(u64)),
+                    )
+                    .arg(
+                        Arg::new("no_defer_decryption")
+                            .long("no-defer-decryption")
+                            .help("Developer option: decrypt the final response online instead of deferring it until notarization")
+                            .action(clap::ArgAction::SetTrue),
+                    )
+                    .arg(
+                        overridable_flag(Arg::new("fail_on_truncation")
+                            .long("fail-on-truncation")
+                            .help("Abort instead of warning when a response is cut off by the model's token limit")
+                            ),
+                    )
+                    .arg(
+                        overridable_flag(Arg::new("compact_proofs")
+                            .long("compact-proofs")
+                            .help("Write the proof as minified JSON instead of pretty-printed JSON")
+                            ),
+                    )
+                    .arg(
+                        Arg::new("history_window")
+                            .long("history-window")
+                            .help("Only resend the last <n> exchanges with each message, plus the setup prompt. Defaults to the full history")
+                            .value_parser(clap::value_parser!(usize)),
+                    )
+                    .arg(
+                        overridable_flag(Arg::new("no_setup_prompt")
+                            .long("no-setup-prompt")
+                            .help("Start the conversation with your first message instead of sending the setup prompt")
+                            ),
+                    )
+                    .arg(
+                        Arg::new("inference_route")
+                            .long("inference-route")
+                            .help("Custom API route to send the conversation to, e.g. for gateways with non-standard paths. Overrides the route of the completion style"),
+                    )
+                    .arg(
+                        Arg::new("max_response_bytes")
+                            .long("max-response-bytes")
+                            .help("Warn when a response body is larger than this many bytes")
+                            .value_parser(clap::value_parser!(usize)),
+                    )
+                    .arg(
+                        overridable_flag(Arg::new("fail_on_oversize")
+                            .long("fail-on-oversize")
+                            .help("Abort instead of warning when a response body is larger than `--max-response-bytes`")
+                            ),
+                    )
+                    .arg(
+                        Arg::new("request_id_header")
+                            .long("request-id-header")
+                            .help("Response header to keep uncensored in the proof and print, e.g. `request-id` for support tickets. Can be repeated")
+                            .action(clap::ArgAction::Append),
+                    )
+                    .arg(
+                        Arg::new("check_credentials")
+                            .long("check-credentials")
+                            .help("Check that the API accepts your API key before setting up the session with the Notary")
+                            .action(clap::ArgAction::SetTrue),
+                    )
+                    .arg(
+                        overridable_flag(Arg::new("verify_on_save")
+                            .long("verify-on-save")
+                            .help("Verify the proof right after saving it, failing if it does not verify")
+                            ),
+                    )
+                    .arg(
+                        Arg::new("notary_pubkey")
+                            .long("notary-pubkey")
+                            .help("Path to a PEM public key of a trusted Notary to verify the saved proof against, can be repeated. Defaults to the bundled dummy Notary key")
+                            .value_hint(clap::ValueHint::FilePath)
+                            .value_parser(clap::value_parser!(std::path::PathBuf))
+                            .action(clap::ArgAction::Append),
+                    ),
+            )
+            .subcommand(
+                Command::new("verify-manifest")
+                    .about("Checks that the proof files listed in a batch manifest are present and unmodified")
+                    .arg(
+                        Arg::new("manifest_path")
+                            .help("Path to the JSON manifest file")
+                            .required(true)
+                            .value_parser(clap::value_parser!(std::path::PathBuf))
+                            .index(1),
+                    ),
+            )
+            .subcommand(
+                Command::new("list-models")
+                    .about("Lists the IDs of the models available through the remote API"),
+            )
+            .subcommand(
+                Command::new("verify-attribution")
+                    .about("Verifies the cryptographic proof for a remote model")
+                    .arg(
+                        Arg::new("proof_path")
+                            .help("Path to the JSON proof file")
+                            .required(true)
+                            .index(1),
+                    )
+                    .arg(
+                        Arg::new("notary_pubkey")
+                            .long("notary-pubkey")
+                            .help("Path to a PEM public key of a trusted Notary, can be repeated to trust several keys. Defaults to the bundled dummy Notary key")
+                            .value_hint(clap::ValueHint::FilePath)
+                            .value_parser(clap::value_parser!(std::path::PathBuf))
+                            .action(clap::ArgAction::Append),
+                    ),
+            ),
+    )
+}
+
+/// Makes `arg` a flag that can also be given a value, so that `--flag=false` turns off an option
+/// enabled in the config file
+fn overridable_flag(arg: Arg) -> Arg {
+    arg.num_args(0..=1)
+        .require_equals(true)
+        .default_missing_value("true")
+        .value_parser(clap::value_parser!(bool))
+}
+
+/// Resolves the conversation options from the command line, falling back to the config file
+#[cfg(feature = "remote")]
+fn conversation_options(
+    matches: &clap::ArgMatches,
+    file: remote::ConfigFile,
+) -> Result<remote::ConversationOptions, Box<dyn Error>> {
+    let (extra_headers, header_sources) = merge_headers(
+        file.headers,
+        matches
+            .get_many::<(String, String)>("header")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+    )?;
+
+    let sources = remote::OptionSources {
+        completion_style: option_source(
+            matches,
+            "completion_style",
+            file.completion_style.is_some(),
+        ),
+        reveal_policy: option_source(matches, "reveal", file.reveal.is_some()),
+        extra_headers: header_sources,
+        proof_validity: option_source(matches, "expires_in", file.expires_in.is_some()),
+    };
+
+    let completion_style =
+        match cli_or_file(matches, "completion_style", file.completion_style).as_deref() {
+            Some("text") => remote::CompletionStyle::Text,
+            Some("chat") | None => remote::CompletionStyle::Chat,
+            Some(other) => Err(format!(
+                "Invalid completion style `{other}` in the config file, expected `chat` or `text`"
+            ))?,
+        };
+
+    let reveal_policy = match cli_or_file(matches, "reveal", file.reveal).as_deref() {
+        Some("response") => remote::RevealPolicy::ResponseOnly,
+        Some("request") => remote::RevealPolicy::RequestOnly,
+        Some("both") | None => remote::RevealPolicy::Both,
+        Some(other) => Err(format!(
+            "Invalid reveal policy `{other}` in the config file, expected `both`, `response` or `request`"
+        ))?,
+    };
+
+    for (name, value) in &file.headers {
+        validate_header(name, value)
+            .map_err(|err| format!("Invalid header `{name}` in the config file, {err}"))?;
+    }
+
+    // Headers from the config file are sent first, followed by those on the command line
+    let extra_headers = file
+        .headers
+        .into_iter()
+        .chain(
+            matches
+                .get_many::<(String, String)>("header")
+                .unwrap_or_default()
+                .cloned(),
+        )
+        .collect();
+
+    let verify_on_save =
+        cli_or_file(matches, "verify_on_save", file.verify_on_save).unwrap_or_default();
+    let notary_pubkey_paths = match matches.get_many::<std::path::PathBuf>("notary_pubkey") {
+        // Checked here rather than by clap, as `--verify-on-save` may come from the config file
+        Some(_) if !verify_on_save => Err(
+            "`--notary-pubkey` is only used to verify the proof, it requires `--verify-on-save`",
+        )?,
+        Some(paths) => paths.cloned().collect(),
+        None => file.notary_pubkeys,
+    };
+
+    let options = remote::ConversationOptions {
+        proof_to_stdout: matches.get_flag("stdout"),
+        completion_style,
+        reveal_policy,
+        extra_headers,
+        model_id: matches.get_one::<String>("model").cloned(),
+        config_file_model_id: file.model,
+        print_config: matches.get_flag("print_config"),
+        proof_validity: cli_or_file(matches, "expires_in", file.expires_in)
+            .map(std::time::Duration::from_secs),
+        dump_transcript_dir: matches
+            .get_one::<std::path::PathBuf>("dump_transcript")
+            .cloned(),
+        message: matches.get_one::<String>("message").cloned(),
+        prompts_file: matches
+            .get_one::<std::path::PathBuf>("prompts_file")
+            .cloned(),
+        connect_timeout: cli_or_file(matches, "connect_timeout", file.connect_timeout)
+            .map(std::time::Duration::from_secs),
+        no_defer_decryption: matches.get_flag("no_defer_decryption"),
+        fail_on_truncation: cli_or_file(matches, "fail_on_truncation", file.fail_on_truncation)
+            .unwrap_or_default(),
+        fail_on_oversize: cli_or_file(matches, "fail_on_oversize", file.fail_on_oversize)
+            .unwrap_or_default(),
+        compact_proofs: cli_or_file(matches, "compact_proofs", file.compact_proofs)
+            .unwrap_or_default(),
+        history_window: cli_or_file(matches, "history_window", file.history_window),
+        no_setup_prompt: cli_or_file(matches, "no_setup_prompt", file.no_setup_prompt)
+            .unwrap_or_default(),
+        inference_route: cli_or_file(matches, "inference_route", file.inference_route),
+        max_response_bytes: cli_or_file(matches, "max_response_bytes", file.max_response_bytes),
+        request_id_headers: matches
+            .get_many::<String>("request_id_header")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        check_credentials: matches.get_flag("check_credentials"),
+        verify_on_save,
+        notary_pubkey_paths,
+        sources,
+    };
+
+    Ok(options)
+}
+
+/// Merges the headers from the config file with those given on the command line, which replace the
+/// file's headers of the same name. Returns them along with where each one comes from
+#[cfg(feature = "remote")]
+fn merge_headers(
+    file_headers: std::collections::BTreeMap<String, String>,
+    cli_headers: Vec<(String, String)>,
+) -> Result<(Vec<(String, String)>, Vec<remote::ValueSource>), Box<dyn Error>> {
+    for (name, value) in &file_headers {
+        validate_header(name, value)
+            .map_err(|err| format!("Invalid header `{name}` in the config file, {err}"))?;
+    }
+
+    // Headers from the config file are sent first, followed by those on the command line
+    let file_headers = file_headers
+        .into_iter()
+        .filter(|(name, _)| {
+            !cli_headers
+                .iter()
+                .any(|(cli_name, _)| cli_name.eq_ignore_ascii_case(name))
+        })
+        .map(|header| (header, remote::ValueSource::ConfigFile));
+    let cli_headers = cli_headers
+        .into_iter()
+        .map(|header| (header, remote::ValueSource::CommandLine));
+
+    Ok(file_headers.chain(cli_headers).unzip())
+}
+
+/// Returns the value of `id` given on the command line, falling back to the config file and then
+/// to the argument's default value
+#[cfg(feature = "remote")]
+fn cli_or_file<T: Clone + Send + Sync + 'static>(
+    matches: &clap::ArgMatches,
+    id: &str,
+    file_value: Option<T>,
+) -> Option<T> {
+    if matches.value_source(id) == Some(clap::parser::ValueSource::CommandLine) {
+        return matches.get_one::<T>(id).cloned();
+    }
+    file_value.or_else(|| matches.get_one::<T>(id).cloned())
+}
+
+/// Returns where the value `cli_or_file` resolves for `id` comes from, given whether the config
+/// file sets it
+#[cfg(feature = "remote")]
+fn option_source(matches: &clap::ArgMatches, id: &str, in_file: bool) -> remote::ValueSource {
+    match matches.value_source(id) {
+        Some(clap::parser::ValueSource::CommandLine) => remote::ValueSource::CommandLine,
+        _ if in_file => remote::ValueSource::ConfigFile,
+        _ => remote::ValueSource::Default,
+    }
+}
+
+/// Parses a `Name: Value` header provided on the command line
+fn parse_header(header: &str) -> Result<(String, String), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid header `{header}`, expected the `Name: Value` format"))?;
+
+    let (name, value) = (name.trim(), value.trim());
+    validate_header(name, value).map_err(|err| format!("Invalid header `{header}`, {err}"))?;
+
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Checks that `name` and `value` form a valid HTTP header, so that a typo fails before the costly
+/// setup with the Notary rather than when the first request is built
+#[cfg_attr(not(feature = "remote"), allow(unused_variables))]
+fn validate_header(name: &str, value: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("the header name is empty".to_string());
+    }
+
+    #[cfg(feature = "remote")]
+    {
+        hyper::header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| format!("the header name is invalid: {err}"))?;
+        hyper::header::HeaderValue::from_str(value)
+            .map_err(|err| format!("the header value is invalid: {err}"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "remote"))]
+mod tests {
+    use super::*;
+
+    /// Parses the arguments of the `attributed-conversation` subcommand
+    fn conversation_matches(args: &[&str]) -> clap::ArgMatches {
+        let mut matches = cli()
+            .try_get_matches_from(
+                ["ai-passport", "remote", "attributed-conversation"]
+                    .iter()
+                    .chain(args),
+            )
+            .unwrap();
+        let (_, mut remote_matches) = matches.remove_subcommand().unwrap();
+        let (_, matches) = remote_matches.remove_subcommand().unwrap();
+        matches
+    }
+
+    /// Resolves the conversation options for `args` with the TOML `config` as the config file
+    fn options_with_config(name: &str, config: &str, args: &[&str]) -> remote::ConversationOptions {
+        let path = std::env::temp_dir().join(format!(
+            "passport-config-{}-{name}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, config).unwrap();
+        let file = remote::ConfigFile::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        conversation_options(&conversation_matches(args), file).unwrap()
+    }
+
+    const CONFIG: &str = r#"
+        reveal = "request"
+        history-window = 4
+        verify-on-save = true
+        compact-proofs = true
+
+        [headers]
+        X-Label = "file"
+        X-Team = "research"
+    "#;
+
+    #[test]
+    fn config_file_values_are_used() {
+        let options = options_with_config("file", CONFIG, &[]);
+        assert_eq!(options.reveal_policy, remote::RevealPolicy::RequestOnly);
+        assert_eq!(
+            options.sources.reveal_policy,
+            remote::ValueSource::ConfigFile
+        );
+        assert_eq!(options.history_window, Some(4));
+        assert!(options.verify_on_save);
+        assert!(options.compact_proofs);
+    }
+
+    #[test]
+    fn command_line_overrides_the_config_file() {
+        let options = options_with_config(
+            "override",
+            CONFIG,
+            &[
+                "--reveal",
+                "both",
+                "--history-window",
+                "2",
+                "--verify-on-save=false",
+            ],
+        );
+        assert_eq!(options.reveal_policy, remote::RevealPolicy::Both);
+        assert_eq!(
+            options.sources.reveal_policy,
+            remote::ValueSource::CommandLine
+        );
+        assert_eq!(options.history_window, Some(2));
+        assert!(!options.verify_on_save);
+        // Options not given on the command line still come from the file
+        assert!(options.compact_proofs);
+    }
+
+    #[test]
+    fn command_line_header_replaces_the_file_header() {
+        let options = options_with_config("headers", CONFIG, &["--header", "x-label: cli"]);
+        assert_eq!(
+            options.extra_headers,
+            vec![
+                ("X-Team".to_string(), "research".to_string()),
+                ("x-label".to_string(), "cli".to_string()),
+            ]
+        );
+        assert_eq!(
+            options.sources.extra_headers,
+            vec![
+                remote::ValueSource::ConfigFile,
+                remote::ValueSource::CommandLine
+            ]
+        );
+    }
+
+    #[test]
+    fn notary_pubkey_requires_verify_on_save() {
+        let matches = conversation_matches(&["--notary-pubkey", "notary.pub"]);
+        assert!(conversation_options(&matches, remote::ConfigFile::default()).is_err());
+
+        // Enabling it in the config file is enough
+        let options = options_with_config(
+            "pubkey",
+            "verify-on-save = true",
+            &["--notary-pubkey", "notary.pub"],
+        );
+        assert_eq!(
+            options.notary_pubkey_paths,
+            vec![std::path::PathBuf::from("notary.pub")]
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Conversation options read from a TOML file passed with `--config`.
+///
+/// Every field is optional and named after its command line flag. Flags given on the command
+/// line take precedence over the values in the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigFile {
+    pub model: Option<String>,
+    pub completion_style: Option<String>,
+    pub reveal: Option<String>,
+    /// Headers sent in addition to those given with `--header`
+    pub headers: BTreeMap<String, String>,
+    pub expires_in: Option<u64>,
+    pub connect_timeout: Option<u64>,
+    pub history_window: Option<usize>,
+    pub inference_route: Option<String>,
+    pub max_response_bytes: Option<usize>,
+    pub no_setup_prompt: Option<bool>,
+    pub compact_proofs: Option<bool>,
+    pub fail_on_truncation: Option<bool>,
+    pub fail_on_oversize: Option<bool>,
+    pub verify_on_save: Option<bool>,
+    pub notary_pubkeys: Vec<PathBuf>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the config file at {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse the config file at {}", path.display()))
+    }
+}
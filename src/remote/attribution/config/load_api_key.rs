@@ -1,25 +1,23 @@
+use crate::remote::attribution::config::{env_var_with_source, ValueSource};
 use anyhow::{Context, Result};
-use std::env;
 use std::io::Write;
 
-const API_KEY_ENV_VAR: &str = "REDPILL_API_KEY";
+pub(crate) const API_KEY_ENV_VAR: &str = "REDPILL_API_KEY";
 
-pub(crate) fn load_api_key() -> Result<String> {
-    dotenv::dotenv().ok();
-
-    if let Ok(api_key) = env::var(API_KEY_ENV_VAR) {
+pub(crate) fn load_api_key() -> Result<(String, ValueSource)> {
+    if let Some(api_key) = env_var_with_source(API_KEY_ENV_VAR) {
         return Ok(api_key);
     }
 
     // Prompt the user to enter the API key if not set
-    println!("🔑 The `{API_KEY_ENV_VAR}` environment variable is not set.");
-    println!("To interact with the models, you need to provide the API key.");
-    println!("If you do not have an API key, you can sign up for one at:");
-    println!("`https://red-pill.ai/keys`");
-    print!("Please now enter your Red Pill API key: ");
-    std::io::stdout()
+    eprintln!("🔑 The `{API_KEY_ENV_VAR}` environment variable is not set.");
+    eprintln!("To interact with the models, you need to provide the API key.");
+    eprintln!("If you do not have an API key, you can sign up for one at:");
+    eprintln!("`https://red-pill.ai/keys`");
+    eprint!("Please now enter your Red Pill API key: ");
+    std::io::stderr()
         .flush()
-        .context("Failed to flush stdout")?;
+        .context("Failed to flush stderr")?;
 
     // Capture user input for the API key
     let mut api_key_input = String::new();
@@ -28,5 +26,5 @@ pub(crate) fn load_api_key() -> Result<String> {
         .context("Failed to read user API key input")?;
     let api_key = api_key_input.trim().to_string();
 
-    Ok(api_key)
+    Ok((api_key, ValueSource::Prompt))
 }
@@ -1,11 +1,21 @@
-use crate::remote::attribution::config::model_selection::select_model_id;
+use crate::remote::attribution::config::model_selection::{
+    check_credentials, fetch_model_ids, select_model_id, CredentialCheck, MODEL_ID_ENV_VAR,
+};
+use crate::remote::verify_attribution::trusted_notary_pubkeys;
 use anyhow::{Context, Result};
-use load_api_key::load_api_key;
+use load_api_key::{load_api_key, API_KEY_ENV_VAR};
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
 use std::sync::LazyLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+mod config_file;
 mod load_api_key;
 mod model_selection;
 
+pub use config_file::ConfigFile;
+
 static SETUP_PROMPT: LazyLock<&str> =
     LazyLock::new(|| "Model Prompt: YOU ARE GOING TO BE ACTING AS A HELPFUL ASSISTANT");
 
@@ -14,7 +24,9 @@ static SETUP_PROMPT: LazyLock<&str> =
 pub struct ModelApiSettings {
     pub server_domain: &'static str,
     pub inference_route: &'static str,
+    pub completion_route: &'static str,
     pub model_list_route: &'static str,
+    pub default_model_id: &'static str,
     pub api_key: String,
 }
 
@@ -23,7 +35,9 @@ impl ModelApiSettings {
         Self {
             server_domain: "api.red-pill.ai",
             inference_route: "/v1/chat/completions",
+            completion_route: "/v1/completions",
             model_list_route: "/v1/models",
+            default_model_id: "gpt-4o",
             api_key,
         }
     }
@@ -34,6 +48,8 @@ pub struct NotarySettings {
     pub host: &'static str,
     pub port: u16,
     pub path: &'static str,
+    /// Whether to defer the decryption of the final response until the session is notarized
+    pub defer_decryption: bool,
 }
 
 /// Configuration for Notary settings, defining host, port, and path
@@ -43,50 +59,234 @@ impl Default for NotarySettings {
             host: "notary.pse.dev",
             port: 443,
             path: "v0.1.0-alpha.6",
+            defer_decryption: true,
         }
     }
 }
 
+/// Which side of the conversation is revealed in the proof
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RevealPolicy {
+    /// Reveal both the requests and the responses
+    #[default]
+    Both,
+    /// Reveal only the responses, keeping the requests committed but hidden
+    ResponseOnly,
+    /// Reveal only the requests, keeping the responses committed but hidden
+    RequestOnly,
+}
+
+impl RevealPolicy {
+    pub fn reveals_request(&self) -> bool {
+        matches!(self, RevealPolicy::Both | RevealPolicy::RequestOnly)
+    }
+
+    pub fn reveals_response(&self) -> bool {
+        matches!(self, RevealPolicy::Both | RevealPolicy::ResponseOnly)
+    }
+}
+
 /// Privacy settings including topics to censor in requests and responses
 #[derive(Debug, Default)]
 pub struct PrivacySettings {
     pub request_topics_to_censor: &'static [&'static str],
-    pub response_topics_to_censor: &'static [&'static str],
+    pub response_topics_to_censor: Vec<&'static str>,
+    /// Response headers kept uncensored at the user's request, e.g. for correlation with provider logs
+    pub kept_response_headers: Vec<String>,
+    pub reveal_policy: RevealPolicy,
 }
 
 impl PrivacySettings {
-    fn new() -> Self {
+    fn new(reveal_policy: RevealPolicy, kept_response_headers: &[String]) -> Self {
+        let kept_response_headers = kept_response_headers
+            .iter()
+            .map(|name| name.to_ascii_lowercase())
+            .collect::<Vec<_>>();
+
+        let response_topics_to_censor = [
+            "anthropic-ratelimit-requests-reset",
+            "anthropic-ratelimit-tokens-reset",
+            "request-id",
+            "x-kong-request-id",
+            "cf-ray",
+            "server-timing",
+            "report-to",
+        ]
+        .into_iter()
+        .filter(|topic| !kept_response_headers.iter().any(|kept| kept == topic))
+        .collect();
+
         Self {
             request_topics_to_censor: &["authorization"],
-            response_topics_to_censor: &[
-                "anthropic-ratelimit-requests-reset",
-                "anthropic-ratelimit-tokens-reset",
-                "request-id",
-                "x-kong-request-id",
-                "cf-ray",
-                "server-timing",
-                "report-to",
-            ],
+            response_topics_to_censor,
+            kept_response_headers,
+            reveal_policy,
         }
     }
 }
 
+/// Shape of the requests sent to the model's API
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionStyle {
+    /// Chat completions with a list of `messages`, replies in `choices[0].message.content`
+    #[default]
+    Chat,
+    /// Legacy text completions with a single `prompt`, replies in `choices[0].text`
+    Text,
+}
+
 /// Model settings including API settings, model ID, and setup prompt
 #[derive(Debug)]
 pub struct ModelSettings {
     pub api_settings: ModelApiSettings,
     pub id: String,
-    pub setup_prompt: &'static str,
+    /// Prompt sent as the first message of the conversation, if any
+    pub setup_prompt: Option<&'static str>,
+    pub completion_style: CompletionStyle,
+    /// Route set by the user, taking precedence over the completion style's default route
+    pub custom_inference_route: Option<String>,
 }
 
 impl ModelSettings {
-    fn new(model_id: String, api_settings: ModelApiSettings) -> Self {
+    fn new(
+        model_id: String,
+        api_settings: ModelApiSettings,
+        completion_style: CompletionStyle,
+        with_setup_prompt: bool,
+        custom_inference_route: Option<String>,
+    ) -> Self {
         Self {
             api_settings,
             id: model_id,
-            setup_prompt: *SETUP_PROMPT,
+            setup_prompt: with_setup_prompt.then_some(*SETUP_PROMPT),
+            completion_style,
+            custom_inference_route,
         }
     }
+
+    /// Route of the API endpoint, either set by the user or matching the configured completion style
+    pub fn inference_route(&self) -> &str {
+        if let Some(route) = &self.custom_inference_route {
+            return route;
+        }
+
+        match self.completion_style {
+            CompletionStyle::Chat => self.api_settings.inference_route,
+            CompletionStyle::Text => self.api_settings.completion_route,
+        }
+    }
+}
+
+/// Options for the attributed conversation provided by the user on the command line
+#[derive(Debug, Default)]
+pub struct ConversationOptions {
+    /// Print the proof to stdout instead of saving it to a file
+    pub proof_to_stdout: bool,
+    /// Whether to use the chat or the legacy text completions endpoint
+    pub completion_style: CompletionStyle,
+    /// Which side of the conversation is revealed in the proof
+    pub reveal_policy: RevealPolicy,
+    /// Additional headers sent with every request to the model API
+    pub extra_headers: Vec<(String, String)>,
+    /// Model to talk to, skipping the interactive selection
+    pub model_id: Option<String>,
+    /// Model set in the config file, used when no model is given on the command line
+    pub config_file_model_id: Option<String>,
+    /// Print the resolved configuration and where each value came from
+    pub print_config: bool,
+    /// How long the proof remains valid for, committed to in every request
+    pub proof_validity: Option<Duration>,
+    /// Directory to write the raw, uncensored transcripts to before notarization
+    pub dump_transcript_dir: Option<PathBuf>,
+    /// Single message to send instead of reading the conversation from stdin
+    pub message: Option<String>,
+    /// File with one prompt per line, each attested in its own session with its own proof
+    pub prompts_file: Option<PathBuf>,
+    /// Time allowed to connect to the model's API server
+    pub connect_timeout: Option<Duration>,
+    /// Keep decrypting the final response online instead of deferring it, for debugging
+    pub no_defer_decryption: bool,
+    /// Abort the conversation instead of warning when a response is truncated by the token limit
+    pub fail_on_truncation: bool,
+    /// Write the proof as minified JSON instead of pretty-printing it
+    pub compact_proofs: bool,
+    /// Number of previous exchanges to resend with each message, besides the setup exchange
+    pub history_window: Option<usize>,
+    /// Start the conversation with the user's first message instead of the setup prompt
+    pub no_setup_prompt: bool,
+    /// Route to send the conversation to instead of the completion style's default route
+    pub inference_route: Option<String>,
+    /// Response body size above which the model's reply is warned about, in bytes
+    pub max_response_bytes: Option<usize>,
+    /// Abort the conversation instead of warning when a response is over `max_response_bytes`
+    pub fail_on_oversize: bool,
+    /// Response headers to keep uncensored in the proof and print, for correlation with provider logs
+    pub request_id_headers: Vec<String>,
+    /// Check that the API accepts the API key before setting up the session
+    pub check_credentials: bool,
+    /// Verify the proof right after saving it
+    pub verify_on_save: bool,
+    /// Notary keys to verify the saved proof against, the bundled dummy Notary key if empty
+    pub notary_pubkey_paths: Vec<PathBuf>,
+    /// Where the options shown in the configuration summary were resolved from
+    pub sources: OptionSources,
+}
+
+/// Sources of the options that can be given on the command line or in the config file
+#[derive(Debug, Default)]
+pub struct OptionSources {
+    pub completion_style: ValueSource,
+    pub reveal_policy: ValueSource,
+    /// The source of each of the extra headers, in order
+    pub extra_headers: Vec<ValueSource>,
+    pub proof_validity: ValueSource,
+}
+
+/// Where a configuration value was resolved from
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ValueSource {
+    #[default]
+    Default,
+    CommandLine,
+    ConfigFile,
+    Environment,
+    DotEnv,
+    Prompt,
+}
+
+impl fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self {
+            ValueSource::Default => "default",
+            ValueSource::CommandLine => "command line",
+            ValueSource::ConfigFile => "config file",
+            ValueSource::Environment => "environment",
+            ValueSource::DotEnv => ".env file",
+            ValueSource::Prompt => "interactive prompt",
+        };
+        write!(f, "{source}")
+    }
+}
+
+/// Sources of the configuration values that can be provided in more than one way
+#[derive(Debug)]
+pub struct ConfigSources {
+    pub api_key: ValueSource,
+    pub model_id: ValueSource,
+}
+
+/// Reads a variable from the environment, falling back to the `.env` file.
+/// The `.env` file is read without modifying the environment, so that the source can be reported.
+pub(crate) fn env_var_with_source(name: &str) -> Option<(String, ValueSource)> {
+    if let Ok(value) = env::var(name) {
+        return Some((value, ValueSource::Environment));
+    }
+
+    dotenv::dotenv_iter()
+        .ok()?
+        .filter_map(|item| item.ok())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| (value, ValueSource::DotEnv))
 }
 
 /// Complete application configuration including model, privacy, and notary settings
@@ -95,28 +295,243 @@ pub struct Config {
     pub model_settings: ModelSettings,
     pub privacy_settings: PrivacySettings,
     pub notary_settings: NotarySettings,
+    pub options: ConversationOptions,
+    pub sources: ConfigSources,
+    /// UNIX timestamp after which the proof should no longer be accepted
+    pub proof_expires_at: Option<u64>,
 }
 
 impl Config {
-    fn new(model_settings: ModelSettings) -> Self {
+    fn new(
+        model_settings: ModelSettings,
+        options: ConversationOptions,
+        sources: ConfigSources,
+    ) -> Self {
+        let proof_expires_at = expiry_from_now(options.proof_validity);
+
         Self {
             model_settings,
-            privacy_settings: PrivacySettings::new(),
-            notary_settings: NotarySettings::default(),
+            privacy_settings: PrivacySettings::new(
+                options.reveal_policy,
+                &options.request_id_headers,
+            ),
+            notary_settings: NotarySettings {
+                defer_decryption: !options.no_defer_decryption,
+                ..NotarySettings::default()
+            },
+            options,
+            sources,
+            proof_expires_at,
         }
     }
+
+    /// Starts the validity period of the next proof from now, so that every proof of a batch is
+    /// valid for as long as the first
+    pub(super) fn restart_proof_validity(&mut self) {
+        self.proof_expires_at = expiry_from_now(self.options.proof_validity);
+    }
+
+    /// Prints each resolved setting along with where it was resolved from
+    pub fn print_summary(&self) {
+        let env_source = |source: ValueSource, var: &str| match source {
+            ValueSource::Environment | ValueSource::DotEnv => format!("{source} `{var}`"),
+            source => source.to_string(),
+        };
+
+        let notary = if cfg!(feature = "dummy-notary") {
+            "local dummy notary (`dummy-notary` feature)".to_string()
+        } else {
+            format!(
+                "{}:{}/{} (default)",
+                self.notary_settings.host, self.notary_settings.port, self.notary_settings.path
+            )
+        };
+
+        eprintln!("⚙️ Resolved configuration:");
+        eprintln!(
+            "   API domain:       {} ({})",
+            self.model_settings.api_settings.server_domain,
+            ValueSource::Default
+        );
+        eprintln!(
+            "   API key:          {} ({})",
+            mask_secret(&self.model_settings.api_settings.api_key),
+            env_source(self.sources.api_key, API_KEY_ENV_VAR)
+        );
+        eprintln!(
+            "   Model:            {} ({})",
+            self.model_settings.id,
+            env_source(self.sources.model_id, MODEL_ID_ENV_VAR)
+        );
+        eprintln!(
+            "   Completion style: {:?} ({})",
+            self.model_settings.completion_style, self.options.sources.completion_style
+        );
+        eprintln!(
+            "   Reveal policy:    {:?} ({})",
+            self.privacy_settings.reveal_policy, self.options.sources.reveal_policy
+        );
+        if !self.options.extra_headers.is_empty() {
+            let names = self
+                .options
+                .extra_headers
+                .iter()
+                .zip(&self.options.sources.extra_headers)
+                .map(|((name, _), source)| format!("{name} ({source})"))
+                .collect::<Vec<_>>();
+            eprintln!("   Extra headers:    {}", names.join(", "));
+        }
+        if let Some(expires_at) = self.proof_expires_at {
+            eprintln!(
+                "   Proof expires at: {} ({})",
+                expires_at, self.options.sources.proof_validity
+            );
+        }
+        eprintln!("   Notary:           {notary}");
+    }
+}
+
+/// Returns the UNIX timestamp at which a proof valid for `validity` from now expires
+fn expiry_from_now(validity: Option<Duration>) -> Option<u64> {
+    validity.map(|validity| {
+        (SystemTime::now() + validity)
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs()
+    })
+}
+
+/// Hides all but the edges of a secret so that it can be recognised without being leaked
+fn mask_secret(secret: &str) -> String {
+    let chars = secret.chars().collect::<Vec<_>>();
+    if chars.len() <= 12 {
+        return "*".repeat(chars.len());
+    }
+
+    let start = chars[..4].iter().collect::<String>();
+    let end = chars[chars.len() - 4..].iter().collect::<String>();
+    format!("{start}…{end}")
+}
+
+/// Lists the IDs of the models available through the API, which needs no API key
+pub(super) async fn available_model_ids() -> Result<Vec<String>> {
+    fetch_model_ids(&ModelApiSettings::new(String::new())).await
 }
 
 /// Setup configuration by loading API key, selecting a model, and returning Config
-pub(super) async fn setup_config() -> Result<Config> {
-    let api_key = load_api_key().context("Failed to load API key")?;
-    let api_settings = ModelApiSettings::new(api_key.clone());
+pub(super) async fn setup_config(options: ConversationOptions) -> Result<Config> {
+    // The expiry is committed to in the request headers, so a proof hiding the requests would carry
+    // no expiry at all and be accepted forever
+    if options.proof_validity.is_some() && !options.reveal_policy.reveals_request() {
+        anyhow::bail!(
+            "An expiry time can only be enforced when the requests are revealed, use `--reveal both` or `--reveal request` with `--expires-in`"
+        );
+    }
+
+    // A missing or unreadable key would otherwise only be found after the session was paid for
+    if options.verify_on_save {
+        trusted_notary_pubkeys(&options.notary_pubkey_paths)
+            .map_err(anyhow::Error::msg)
+            .context("Failed to load the Notary keys to verify the proof against")?;
+    }
+
+    let (api_key, api_key_source) = load_api_key().context("Failed to load API key")?;
+    let api_settings = ModelApiSettings::new(api_key);
+
+    let (model_id, model_id_source) = select_model_id(
+        &api_settings,
+        options.model_id.as_deref(),
+        options.config_file_model_id.as_deref(),
+    )
+    .await
+    .context("Failed to select model")?;
+
+    let model_settings = ModelSettings::new(
+        model_id,
+        api_settings,
+        options.completion_style,
+        !options.no_setup_prompt,
+        options.inference_route.clone(),
+    );
+
+    // A bad API key would otherwise only surface after the costly setup with the Notary
+    if options.check_credentials {
+        let route = model_settings.inference_route();
+        let domain = model_settings.api_settings.server_domain;
+        match check_credentials(&model_settings.api_settings, route)
+            .await
+            .context("Failed to validate the API credentials")?
+        {
+            CredentialCheck::Accepted => {
+                eprintln!("✅ The API key was accepted by `{domain}`.")
+            }
+            CredentialCheck::Unverifiable => eprintln!(
+                "⚠️ `{domain}{route}` answers without an API key, so the key could not be checked."
+            ),
+        }
+    }
+
+    let sources = ConfigSources {
+        api_key: api_key_source,
+        model_id: model_id_source,
+    };
+
+    Ok(Config::new(model_settings, options, sources))
+}
 
-    let model_id = select_model_id(&api_settings)
-        .await
-        .context("Failed to select model")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let model_settings = ModelSettings::new(model_id, api_settings);
+    fn model_settings(
+        completion_style: CompletionStyle,
+        custom_inference_route: Option<&str>,
+    ) -> ModelSettings {
+        ModelSettings::new(
+            "gpt-4o".to_string(),
+            ModelApiSettings::new(String::new()),
+            completion_style,
+            true,
+            custom_inference_route.map(str::to_string),
+        )
+    }
 
-    Ok(Config::new(model_settings))
+    #[test]
+    fn completion_style_selects_the_default_route() {
+        assert_eq!(
+            model_settings(CompletionStyle::Chat, None).inference_route(),
+            "/v1/chat/completions"
+        );
+        assert_eq!(
+            model_settings(CompletionStyle::Text, None).inference_route(),
+            "/v1/completions"
+        );
+    }
+
+    #[test]
+    fn explicit_route_wins_over_the_default() {
+        for completion_style in [CompletionStyle::Chat, CompletionStyle::Text] {
+            assert_eq!(
+                model_settings(completion_style, Some("/openai/v1/chat/completions"))
+                    .inference_route(),
+                "/openai/v1/chat/completions"
+            );
+        }
+    }
+
+    #[test]
+    fn kept_request_id_is_no_longer_censored() {
+        let privacy_settings =
+            PrivacySettings::new(RevealPolicy::Both, &["Request-Id".to_string()]);
+
+        assert!(!privacy_settings
+            .response_topics_to_censor
+            .contains(&"request-id"));
+        // The other correlation headers and the API key stay censored
+        assert!(privacy_settings
+            .response_topics_to_censor
+            .contains(&"cf-ray"));
+        assert_eq!(privacy_settings.request_topics_to_censor, ["authorization"]);
+        assert_eq!(privacy_settings.kept_response_headers, ["request-id"]);
+    }
 }
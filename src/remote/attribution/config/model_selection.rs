@@ -1,51 +1,87 @@
-use crate::remote::attribution::config::ModelApiSettings;
+use crate::remote::attribution::config::{env_var_with_source, ModelApiSettings, ValueSource};
 use anyhow::{Context, Result};
 use http_body_util::BodyExt;
-use http_body_util::Empty;
+use http_body_util::{Empty, Full};
 use hyper::body::Bytes;
-use hyper::Method;
+use hyper::body::Incoming;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::{Method, StatusCode};
 use hyper_tls::HttpsConnector;
 use hyper_util::client::legacy::Client;
 use hyper_util::rt::TokioExecutor;
 use serde::Deserialize;
 use std::io::Write;
 
-pub(crate) async fn select_model_id(api_settings: &ModelApiSettings) -> Result<String> {
+pub(crate) const MODEL_ID_ENV_VAR: &str = "MODEL_ID";
+
+/// Selects the model to talk to, in order of precedence from the `--model` flag,
+/// the config file, the `MODEL_ID` environment variable, or interactively
+pub(crate) async fn select_model_id(
+    api_settings: &ModelApiSettings,
+    model_id_flag: Option<&str>,
+    config_file_model_id: Option<&str>,
+) -> Result<(String, ValueSource)> {
+    if let Some(model_id) = model_id_flag {
+        return Ok((model_id.to_string(), ValueSource::CommandLine));
+    }
+
+    if let Some(model_id) = config_file_model_id {
+        return Ok((model_id.to_string(), ValueSource::ConfigFile));
+    }
+
+    // An explicitly configured model takes precedence over the interactive selection
+    if let Some((model_id, source)) = env_var_with_source(MODEL_ID_ENV_VAR) {
+        let model_id = model_id.trim();
+        if !model_id.is_empty() {
+            eprintln!("🤖 Using the `{model_id}` model set by the `{MODEL_ID_ENV_VAR}` environment variable.");
+            return Ok((model_id.to_string(), source));
+        }
+    }
+
     loop {
-        println!("🤖 Please select a model to interact with:");
-        println!("1️⃣ OpenAI gpt-4o (default)");
-        println!("2️⃣ Claude-3.5-Sonnet");
-        println!("3️⃣ Mistral-8b");
-        println!("💡 Or provide a custom model ID. Please visit `https://red-pill.ai/model-list` to view available model IDs.");
-        print!("👉 Your choice: ");
-        std::io::stdout()
+        eprintln!("🤖 Please select a model to interact with:");
+        eprintln!("1️⃣ OpenAI {} (default)", api_settings.default_model_id);
+        eprintln!("2️⃣ Claude-3.5-Sonnet");
+        eprintln!("3️⃣ Mistral-8b");
+        eprintln!("💡 Or provide a custom model ID. Please visit `https://red-pill.ai/model-list` to view available model IDs.");
+        eprint!("👉 Your choice: ");
+        std::io::stderr()
             .flush()
-            .context("Failed to flush stdout")?;
+            .context("Failed to flush stderr")?;
 
         let choice = read_user_input("selection").await?;
-        let model_id = match choice.trim() {
-            "1" => "gpt-4o".to_string(),
-            "2" => "anthropic/claude-3-5-sonnet".to_string(),
-            "3" => "mistralai/ministral-8b".to_string(),
-            custom_model => {
+        let model_id = match menu_model_id(choice.trim(), api_settings) {
+            Some(model_id) => model_id.to_string(),
+            None => {
+                let custom_model = choice.trim();
                 if validate_model_id(custom_model, api_settings).await? {
                     custom_model.to_string()
                 } else {
-                    println!("❌ Invalid model ID. Please enter a valid model ID from the list or provide a custom model ID.");
+                    eprintln!("❌ Invalid model ID. Please enter a valid model ID from the list or provide a custom model ID.");
                     continue;
                 }
             }
         };
 
-        return Ok(model_id);
+        return Ok((model_id, ValueSource::Prompt));
+    }
+}
+
+/// Returns the model of a numbered menu entry, the default model for an empty choice
+fn menu_model_id<'a>(choice: &str, api_settings: &'a ModelApiSettings) -> Option<&'a str> {
+    match choice {
+        "" | "1" => Some(api_settings.default_model_id),
+        "2" => Some("anthropic/claude-3-5-sonnet"),
+        "3" => Some("mistralai/ministral-8b"),
+        _ => None,
     }
 }
 
 async fn read_user_input(prompt: &str) -> Result<String> {
-    print!("Please enter your {}: ", prompt);
-    std::io::stdout()
+    eprint!("Please enter your {}: ", prompt);
+    std::io::stderr()
         .flush()
-        .context("Failed to flush stdout")?;
+        .context("Failed to flush stderr")?;
 
     let mut input = String::new();
     std::io::stdin()
@@ -55,6 +91,80 @@ async fn read_user_input(prompt: &str) -> Result<String> {
 }
 
 async fn validate_model_id(model_id: &str, api_settings: &ModelApiSettings) -> Result<bool> {
+    match fetch_model_ids(api_settings).await {
+        Ok(model_ids) => Ok(model_ids.iter().any(|id| id == model_id)),
+        Err(err) => {
+            eprintln!("❌ Error fetching model list: {err:#}");
+            Ok(false)
+        }
+    }
+}
+
+/// Outcome of checking the API key against the API
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CredentialCheck {
+    /// The API key was accepted by a route that rejects unauthenticated requests
+    Accepted,
+    /// The route answers without an API key too, so it cannot tell whether the key is valid
+    Unverifiable,
+}
+
+/// Checks that the API accepts the configured API key on `route`, the inference route of the
+/// conversation. The request carries an empty body, so that it is rejected before any completion
+/// is generated and billed
+pub(crate) async fn check_credentials(
+    api_settings: &ModelApiSettings,
+    route: &str,
+) -> Result<CredentialCheck> {
+    let is_auth_error =
+        |status: StatusCode| matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN);
+
+    // Some routes, like the model list, answer without an API key, and would accept any key
+    let unauthenticated = request_inference_probe(api_settings, route, None).await?;
+    if !is_auth_error(unauthenticated.status()) {
+        return Ok(CredentialCheck::Unverifiable);
+    }
+
+    let response =
+        request_inference_probe(api_settings, route, Some(&api_settings.api_key)).await?;
+    match response.status() {
+        status if is_auth_error(status) => {
+            anyhow::bail!("The API rejected the API key with {status}, please check the key")
+        }
+        status if status.is_server_error() => anyhow::bail!("The API responded with {status}"),
+        // Any other answer, typically a complaint about the empty body, got past authentication
+        _ => Ok(CredentialCheck::Accepted),
+    }
+}
+
+/// Sends an empty JSON body to `route`, authenticated with `api_key` if given
+async fn request_inference_probe(
+    api_settings: &ModelApiSettings,
+    route: &str,
+    api_key: Option<&str>,
+) -> Result<hyper::Response<Incoming>> {
+    let mut request_builder = hyper::Request::builder()
+        .method(Method::POST)
+        .uri(format!("https://{}{}", api_settings.server_domain, route))
+        .header(CONTENT_TYPE, "application/json");
+    if let Some(api_key) = api_key {
+        request_builder = request_builder.header(AUTHORIZATION, format!("Bearer {api_key}"));
+    }
+    let request = request_builder
+        .body(Full::new(Bytes::from_static(b"{}")))
+        .context("Failed to build request")?;
+
+    let https = HttpsConnector::new();
+    let client = Client::builder(TokioExecutor::new()).build::<_, _>(https);
+
+    client
+        .request(request)
+        .await
+        .context("Failed to send request to API")
+}
+
+/// Fetches the IDs of the models served by the API
+pub(crate) async fn fetch_model_ids(api_settings: &ModelApiSettings) -> Result<Vec<String>> {
     #[derive(Debug, Deserialize)]
     struct Model {
         id: String,
@@ -65,6 +175,26 @@ async fn validate_model_id(model_id: &str, api_settings: &ModelApiSettings) -> R
         data: Vec<Model>,
     }
 
+    let response = request_model_list(api_settings).await?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("The API responded with {}", response.status());
+    }
+
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .context("Failed to read response body")?
+        .to_bytes();
+    let model_list: ModelList =
+        serde_json::from_slice(&body).context("Failed to deserialize model list")?;
+
+    Ok(model_list.data.into_iter().map(|model| model.id).collect())
+}
+
+/// Sends an unauthenticated request to the model list route
+async fn request_model_list(api_settings: &ModelApiSettings) -> Result<hyper::Response<Incoming>> {
     let request = hyper::Request::builder()
         .method(Method::GET)
         .uri(format!(
@@ -77,24 +207,76 @@ async fn validate_model_id(model_id: &str, api_settings: &ModelApiSettings) -> R
     let https = HttpsConnector::new();
     let client = Client::builder(TokioExecutor::new()).build::<_, _>(https);
 
-    let response = client
+    client
         .request(request)
         .await
-        .context("Failed to send request to API")?;
-
-    if response.status().is_success() {
-        let body = response
-            .into_body()
-            .collect()
-            .await
-            .context("Failed to read response body")?
-            .to_bytes();
-        let model_list: ModelList =
-            serde_json::from_slice(&body).context("Failed to deserialize model list")?;
-
-        Ok(model_list.data.iter().any(|model| model.id == model_id))
-    } else {
-        eprintln!("❌ Error fetching model list: {}", response.status());
-        Ok(false)
+        .context("Failed to send request to API")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::sync::Mutex;
+
+    /// The tests below set `MODEL_ID`, which is shared by the whole test process
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn empty_and_first_choices_select_the_default_model() {
+        let api_settings = ModelApiSettings::new(String::new());
+        assert_eq!(
+            menu_model_id("", &api_settings),
+            Some(api_settings.default_model_id)
+        );
+        assert_eq!(
+            menu_model_id("1", &api_settings),
+            Some(api_settings.default_model_id)
+        );
+        assert_eq!(menu_model_id("my/custom-model", &api_settings), None);
+    }
+
+    /// Selects the model with `MODEL_ID` set to `model_id_var`, on a runtime of its own so that the
+    /// lock on the environment is not held across an await
+    fn select_with_model_id_var(
+        model_id_var: &str,
+        model_id_flag: Option<&str>,
+        config_file_model_id: Option<&str>,
+    ) -> Result<(String, ValueSource)> {
+        let _guard = ENV_LOCK.lock().unwrap();
+        env::set_var(MODEL_ID_ENV_VAR, model_id_var);
+
+        let api_settings = ModelApiSettings::new(String::new());
+        let selected = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(select_model_id(
+                &api_settings,
+                model_id_flag,
+                config_file_model_id,
+            ));
+        env::remove_var(MODEL_ID_ENV_VAR);
+
+        selected
+    }
+
+    #[test]
+    fn model_id_variable_overrides_the_selection() {
+        assert_eq!(
+            select_with_model_id_var(" mistralai/ministral-8b ", None, None).unwrap(),
+            (
+                "mistralai/ministral-8b".to_string(),
+                ValueSource::Environment
+            )
+        );
+    }
+
+    #[test]
+    fn model_flag_wins_over_the_model_id_variable() {
+        assert_eq!(
+            select_with_model_id_var("mistralai/ministral-8b", Some("gpt-4o"), Some("file/model"))
+                .unwrap(),
+            ("gpt-4o".to_string(), ValueSource::CommandLine)
+        );
     }
 }
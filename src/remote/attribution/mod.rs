@@ -2,64 +2,226 @@ mod config;
 mod setup_notary;
 mod tlsn_operations;
 
-use crate::remote::attribution::config::{setup_config, Config, ModelSettings};
-use crate::remote::attribution::setup_notary::setup_connections;
+pub use config::{
+    CompletionStyle, ConfigFile, ConversationOptions, OptionSources, RevealPolicy, ValueSource,
+};
+
+use crate::remote::attribution::config::{available_model_ids, setup_config, Config};
+use crate::remote::attribution::setup_notary::{connect_timeout, setup_connections};
 use crate::remote::attribution::tlsn_operations::{
-    build_proof, extract_private_data, notarise_session,
+    build_proof, extract_private_data, notarise_session, redacted_headers,
 };
+use crate::remote::manifest::Manifest;
+use crate::remote::verify_attribution::{verify_proof_file, verify_proof_json};
+use crate::remote::PROOF_EXPIRY_HEADER;
 use anyhow::{Context, Result};
 use http_body_util::BodyExt;
 use hyper::client::conn::http1::SendRequest;
 use hyper::header::{AUTHORIZATION, CONNECTION, CONTENT_TYPE, HOST};
 use hyper::{Method, StatusCode};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str;
 use tlsn_prover::tls::ProverControl;
 use tracing::{debug, warn};
 
-pub async fn generate_conversation_attribution() -> Result<()> {
+/// Prints the IDs of the models that can be used in a conversation, one per line
+pub async fn list_models() -> Result<()> {
+    let model_ids = available_model_ids()
+        .await
+        .context("Error fetching the model list")?;
+
+    for model_id in model_ids {
+        println!("{model_id}");
+    }
+
+    Ok(())
+}
+
+pub async fn generate_conversation_attribution(options: ConversationOptions) -> Result<()> {
     // Print the rules on how to use the application
-    println!("🌟 Welcome to the Multi-Model Prover CLI! 🌟");
-    println!("This application allows you to interact with various AI models and then generate a cryptographic proof of your conversation.");
+    eprintln!("🌟 Welcome to the Multi-Model Prover CLI! 🌟");
+    eprintln!("This application allows you to interact with various AI models and then generate a cryptographic proof of your conversation.");
 
-    println!("⚙️ First, you will need to set up your assistant model.");
-    let config = setup_config().await.context("Error setting up config")?;
+    eprintln!("⚙️ First, you will need to set up your assistant model.");
+    let mut config = setup_config(options)
+        .await
+        .context("Error setting up config")?;
+
+    if config.options.print_config {
+        config.print_summary();
+    }
 
-    println!("🔐 Next, please wait while the system is setup...");
+    match config.options.prompts_file.clone() {
+        Some(prompts_file) => {
+            let prompts = read_prompts(&prompts_file)?;
+            let run_id = RunId::new(&config.model_settings.id);
+            // The proofs are saved to the working directory, as is the manifest
+            attest_batch(&mut config, &prompts, &run_id, Path::new("")).await?;
+        }
+        None => {
+            attest_conversation(&config, None).await?;
+        }
+    }
 
-    let (prover_ctrl, prover_task, mut request_sender) = setup_connections(&config)
+    #[cfg(feature = "dummy-notary")]
+    {
+        let public_key = include_str!("../../../tlsn/notary.pub");
+
+        // Dummy notary is used for testing purposes only
+        // It is not secure and should not be used in production
+        eprintln!("🚨 PUBLIC KEY: \n{}", public_key);
+        eprintln!("🚨 WARNING: Dummy notary is used for testing purposes only. It is not secure and should not be used in production.");
+    }
+
+    Ok(())
+}
+
+/// Reads the prompts of a batch, one per non-empty line
+fn read_prompts(path: &Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read the prompts file at {}", path.display()))?;
+    let prompts = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    if prompts.is_empty() {
+        anyhow::bail!("The prompts file at {} contains no prompts", path.display());
+    }
+    Ok(prompts)
+}
+
+/// Attests the single message prompts of a batch
+trait AttestPrompt {
+    /// Attests `prompt` in its own session as proof `number` of the batch, returning the path of
+    /// the saved proof
+    async fn attest_prompt(
+        &mut self,
+        run_id: &RunId,
+        number: usize,
+        prompt: &str,
+    ) -> Result<Option<PathBuf>>;
+}
+
+impl AttestPrompt for Config {
+    async fn attest_prompt(
+        &mut self,
+        run_id: &RunId,
+        number: usize,
+        prompt: &str,
+    ) -> Result<Option<PathBuf>> {
+        self.options.message = Some(prompt.to_string());
+        self.restart_proof_validity();
+        attest_conversation(self, Some((run_id, number))).await
+    }
+}
+
+/// Attests every prompt in its own session, so that each proof stands on its own, then writes the
+/// manifest of the saved proofs to `dir`. A failed prompt ends the batch, but the proofs saved
+/// before it are still listed in the manifest
+async fn attest_batch(
+    attester: &mut impl AttestPrompt,
+    prompts: &[String],
+    run_id: &RunId,
+    dir: &Path,
+) -> Result<()> {
+    let mut proof_paths = Vec::new();
+    let mut result = Ok(());
+    for (index, prompt) in prompts.iter().enumerate() {
+        eprintln!(
+            "\n📨 Attesting prompt {} of {}...",
+            index + 1,
+            prompts.len()
+        );
+        match attester
+            .attest_prompt(run_id, index + 1, prompt)
+            .await
+            .context(format!("Error attesting prompt #{}", index + 1))
+        {
+            Ok(proof_path) => proof_paths.extend(proof_path),
+            Err(err) => {
+                result = Err(err);
+                break;
+            }
+        }
+    }
+
+    // The manifest lets a verifier check that no proof of the batch was dropped or altered
+    if !proof_paths.is_empty() {
+        match save_manifest(run_id, dir, &proof_paths) {
+            Ok(manifest_path) => eprintln!(
+                "\n🗂️ Manifest of the {} proof(s) saved to `{}`.",
+                proof_paths.len(),
+                manifest_path.display()
+            ),
+            // The failed prompt is the error worth returning
+            Err(err) if result.is_err() => eprintln!("❌ Failed to save the manifest: {err:#}"),
+            Err(err) => return Err(err),
+        }
+    }
+
+    result
+}
+
+/// Runs one conversation over a notarized session and produces its proof, returning the path of
+/// the saved proof unless it was printed. Batch proofs are named after the batch's run and
+/// numbered, so that their file names do not collide
+async fn attest_conversation(
+    config: &Config,
+    batch_entry: Option<(&RunId, usize)>,
+) -> Result<Option<PathBuf>> {
+    eprintln!("🔐 Next, please wait while the system is setup...");
+
+    let (prover_ctrl, prover_task, mut request_sender, notary_closed) = setup_connections(config)
         .await
         .context("Error setting up connections")?;
 
-    println!(
+    eprintln!(
         "💬 Now, you can engage in a conversation with the `{}` model.",
         config.model_settings.id
     );
-    println!("The assistant will respond to your messages in real time.");
-    println!("📝 When you're done, simply type 'exit' or press `Enter` without typing a message to end the conversation.");
+    eprintln!("The assistant will respond to your messages in real time.");
+    eprintln!("📝 When you're done, simply type 'exit' or press `Enter` without typing a message to end the conversation.");
 
-    println!("🔒 Once finished, a proof of the conversation will be generated and saved for your records.");
+    eprintln!("🔒 Once finished, a proof of the conversation will be generated and saved for your records.");
 
-    println!("✨ Let's get started! Once the setup is complete, you can begin the conversation.\n");
+    eprintln!(
+        "✨ Let's get started! Once the setup is complete, you can begin the conversation.\n"
+    );
 
     let mut messages = vec![];
 
     let mut request_index = 1;
 
-    let mut recv_private_data = vec![];
-    let mut sent_private_data = vec![];
+    // The API key is censored wherever it appears, as some APIs echo it back in their bodies
+    let api_key = config
+        .model_settings
+        .api_settings
+        .api_key
+        .as_bytes()
+        .to_vec();
+    let (mut recv_private_data, mut sent_private_data) = if api_key.is_empty() {
+        (vec![], vec![])
+    } else {
+        (vec![api_key.clone()], vec![api_key])
+    };
+
+    let mut token_usage = vec![];
 
     loop {
         let stop = single_interaction_round(
             &mut request_sender,
-            &config,
+            config,
             &mut messages,
             request_index,
             &mut recv_private_data,
             &mut sent_private_data,
+            &mut token_usage,
         )
         .await?;
 
@@ -76,42 +238,92 @@ pub async fn generate_conversation_attribution() -> Result<()> {
         prover_ctrl,
         &mut request_sender,
         &mut recv_private_data,
-        &config,
+        config,
     )
     .await;
 
-    println!("🔒 Generating a cryptographic proof of the conversation. Please wait...");
+    eprintln!("🔒 Generating a cryptographic proof of the conversation. Please wait...");
 
     // Notarize the session
     debug!("Notarizing the session...");
-    let notarised_session = notarise_session(prover_task, &recv_private_data, &sent_private_data)
-        .await
-        .context("Error notarizing the session")?;
+    let notarised_session = notarise_session(
+        prover_task,
+        &recv_private_data,
+        &sent_private_data,
+        config.options.dump_transcript_dir.as_deref(),
+        &notary_closed,
+    )
+    .await
+    .context("Error notarizing the session")?;
 
     // Build the proof
     debug!("Building the proof...");
-    let proof = build_proof(notarised_session);
+    let proof = build_proof(notarised_session, config.privacy_settings.reveal_policy);
+
+    let proof_path = if config.options.proof_to_stdout {
+        // Only the proof goes to stdout, so that it can be piped into other tools
+        let proof_content = serialize_proof(&proof, config.options.compact_proofs)?;
+
+        // Verify before printing, so that a proof failing verification is never piped along
+        if config.options.verify_on_save {
+            verify_proof_json(&proof_content, &config.options.notary_pubkey_paths)
+                .map_err(|e| anyhow::anyhow!("The proof failed verification: {e}"))?;
+            eprintln!("✅ Proof successfully verified.");
+        }
+
+        println!("{proof_content}");
+        None
+    } else {
+        // Save the proof to a file
+        let (run_id, proof_number) = match batch_entry {
+            Some((run_id, number)) => (run_id.clone(), Some(number)),
+            None => (RunId::new(&config.model_settings.id), None),
+        };
+        let file_path =
+            save_proof_to_file(&proof, &run_id, config.options.compact_proofs, proof_number)?;
 
-    // Save the proof to a file
-    let file_path = save_proof_to_file(&proof, &config.model_settings.id)?;
+        eprintln!("✅ Proof successfully saved to `{}`.", file_path.display());
 
-    println!("✅ Proof successfully saved to `{}`.", file_path.display());
-    println!(
+        // Catch a corrupt write or an untrusted Notary now rather than when the proof is shared
+        if config.options.verify_on_save {
+            verify_proof_file(
+                &file_path.to_string_lossy(),
+                &config.options.notary_pubkey_paths,
+            )
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "The proof saved to `{}` failed verification: {e}",
+                    file_path.display()
+                )
+            })?;
+            eprintln!("✅ Proof successfully verified.");
+        }
+        Some(file_path)
+    };
+    eprintln!(
         "\n🔍 You can share this proof or inspect it at: https://explorer.tlsnotary.org/.\n\
         📂 Simply upload the proof, and anyone can verify its authenticity and inspect the details."
     );
 
-    #[cfg(feature = "dummy-notary")]
-    {
-        let public_key = include_str!("../../../tlsn/notary.pub");
-
-        // Dummy notary is used for testing purposes only
-        // It is not secure and should not be used in production
-        println!("🚨 PUBLIC KEY: \n{}", public_key);
-        println!("🚨 WARNING: Dummy notary is used for testing purposes only. It is not secure and should not be used in production.");
+    // The token counts are claimed by the model API in the attested responses
+    if !token_usage.is_empty() {
+        let total = token_usage
+            .iter()
+            .fold(TokenUsage::default(), |acc, usage| TokenUsage {
+                prompt_tokens: acc.prompt_tokens + usage.prompt_tokens,
+                completion_tokens: acc.completion_tokens + usage.completion_tokens,
+                total_tokens: acc.total_tokens + usage.total_tokens,
+            });
+        eprintln!(
+            "\n📊 Token usage reported by the model API over {} exchange(s): {} prompt, {} completion, {} total.",
+            token_usage.len(),
+            total.prompt_tokens,
+            total.completion_tokens,
+            total.total_tokens
+        );
     }
 
-    Ok(())
+    Ok(proof_path)
 }
 
 async fn single_interaction_round(
@@ -121,27 +333,47 @@ async fn single_interaction_round(
     request_index: i32,
     recv_private_data: &mut Vec<Vec<u8>>,
     sent_private_data: &mut Vec<Vec<u8>>,
+    token_usage: &mut Vec<TokenUsage>,
 ) -> Result<bool> {
     let mut user_message = String::new();
-    // The first request is the setup prompt
-    if request_index == 1 {
-        user_message = config.model_settings.setup_prompt.to_string();
+    // The first request is the setup prompt, unless it was disabled
+    let setup_prompt = config
+        .model_settings
+        .setup_prompt
+        .filter(|_| request_index == 1);
+    let is_setup_round = setup_prompt.is_some();
+
+    if let Some(setup_prompt) = setup_prompt {
+        user_message = setup_prompt.to_string();
         debug!(
             "Sending setup prompt to `{}` model API: {}",
             config.model_settings.id, user_message
         );
+    } else if let Some(message) = &config.options.message {
+        // In one-shot mode the conversation ends right after the provided message
+        let message_index = if config.model_settings.setup_prompt.is_some() {
+            2
+        } else {
+            1
+        };
+        if request_index > message_index {
+            return Ok(true);
+        }
+        user_message = message.clone();
+        eprintln!("\n💬 Your message:\n> {}", user_message);
+        eprintln!("processing...");
     } else {
-        println!("\n💬 Your message\n(type 'exit' to end): ");
+        eprintln!("\n💬 Your message\n(type 'exit' to end): ");
 
-        print!("> ");
-        std::io::stdout()
+        eprint!("> ");
+        std::io::stderr()
             .flush()
-            .context("Failed to flush stdout")?;
+            .context("Failed to flush stderr")?;
 
         std::io::stdin()
             .read_line(&mut user_message)
             .context("Failed to read user input to the model")?;
-        println!("processing...");
+        eprintln!("processing...");
     }
 
     if user_message.trim().is_empty() || user_message.trim() == "exit" {
@@ -159,7 +391,7 @@ async fn single_interaction_round(
     messages.push(user_message);
 
     // Prepare the Request to send to the model's API
-    let request = generate_request(messages, &config.model_settings)
+    let request = generate_request(messages, config)
         .context(format!("Error generating #{request_index} request"))?;
 
     // Collect the private data transmitted in the request
@@ -169,18 +401,62 @@ async fn single_interaction_round(
         config.privacy_settings.request_topics_to_censor,
     );
 
-    debug!("Request {request_index}: {:?}", request);
+    // Censored headers hold secrets such as the API key, which must not end up in the logs. The
+    // user provided headers may carry secrets of their own, as with an `X-Api-Key`, so they are
+    // masked as well
+    let extra_header_names = config
+        .options
+        .extra_headers
+        .iter()
+        .map(|(name, _)| name.to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    let logged_topics_to_censor = config
+        .privacy_settings
+        .request_topics_to_censor
+        .iter()
+        .copied()
+        .chain(extra_header_names.iter().map(String::as_str))
+        .collect::<Vec<_>>();
+    debug!(
+        "Request {request_index}: {} {} {:?} {}",
+        request.method(),
+        request.uri(),
+        redacted_headers(request.headers(), &logged_topics_to_censor),
+        request.body()
+    );
 
     debug!("Sending request {request_index} to Model's API...");
 
-    let response = request_sender
-        .send_request(request)
-        .await
-        .context(format!("Request #{request_index} failed"))?;
+    let response = request_sender.send_request(request);
+    // The TLS handshake with the server completes with the first request, so an unresponsive
+    // server is only noticed here
+    let response = if request_index == 1 {
+        let connect_timeout = connect_timeout(config);
+        tokio::time::timeout(connect_timeout, response)
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "Timed out after {}s waiting for the TLS handshake and first response from `{}`",
+                    connect_timeout.as_secs(),
+                    config.model_settings.api_settings.server_domain
+                )
+            })?
+    } else {
+        response.await
+    }
+    .context(format!("Request #{request_index} failed"))?;
 
     debug!("Received response {request_index} from Model");
 
-    debug!("Raw response {request_index}: {:?}", response);
+    debug!(
+        "Raw response {request_index}: {:?} {} {:?}",
+        response.version(),
+        response.status(),
+        redacted_headers(
+            response.headers(),
+            &config.privacy_settings.response_topics_to_censor,
+        )
+    );
 
     if response.status() != StatusCode::OK {
         // TODO - do a graceful shutdown
@@ -194,9 +470,19 @@ async fn single_interaction_round(
     extract_private_data(
         recv_private_data,
         response.headers(),
-        config.privacy_settings.response_topics_to_censor,
+        &config.privacy_settings.response_topics_to_censor,
     );
 
+    // The kept headers are revealed in the proof, so they can be quoted to the provider
+    for name in &config.privacy_settings.kept_response_headers {
+        if let Some(value) = response.headers().get(name.as_str()) {
+            eprintln!(
+                "🔖 Response #{request_index} `{name}`: {}",
+                String::from_utf8_lossy(value.as_bytes())
+            );
+        }
+    }
+
     // Collect the body
     let payload = response
         .into_body()
@@ -205,44 +491,280 @@ async fn single_interaction_round(
         .context("Error reading response body")?
         .to_bytes();
 
-    let parsed = serde_json::from_str::<serde_json::Value>(&String::from_utf8_lossy(&payload))
-        .context("Error parsing the response")?;
+    // Oversized replies inflate every later request and the proof, so flag them as soon as they arrive
+    if let Some(max_response_bytes) = config.options.max_response_bytes {
+        if payload.len() > max_response_bytes {
+            if config.options.fail_on_oversize {
+                anyhow::bail!(
+                    "Response #{request_index} is {} bytes, over the limit of {max_response_bytes} bytes",
+                    payload.len()
+                );
+            }
+            warn!(
+                "Response {request_index} is {} bytes, over the limit of {max_response_bytes} bytes",
+                payload.len()
+            );
+            eprintln!(
+                "⚠️ The response is {} bytes, over the limit of {max_response_bytes} bytes, and will make the proof larger.",
+                payload.len()
+            );
+        }
+    }
 
-    // Pretty printing the response
-    debug!(
-        "Response {request_index}: {}",
-        serde_json::to_string_pretty(&parsed).context("Error pretty printing the response")?
-    );
+    // A lossy conversion would silently alter the attested reply, so reject non-text bodies
+    let payload = str::from_utf8(&payload).context(format!(
+        "Response #{request_index} body is not valid UTF-8, expected a JSON text reply"
+    ))?;
+
+    debug!("Response {request_index}: {payload}");
+
+    let parsed = serde_json::from_str::<CompletionResponse>(payload)
+        .context(format!("Error parsing response #{request_index}"))?;
 
     debug!("Request {request_index} to Model succeeded");
 
-    let received_assistant_message = serde_json::json!({"role": "assistant", "content": parsed["choices"][0]["message"]["content"]});
-    messages.push(received_assistant_message);
+    let choice = parsed
+        .choices
+        .into_iter()
+        .next()
+        .context(format!("Response #{request_index} contains no choices"))?;
+
+    let Reply {
+        content: reply_content,
+        other_parts,
+        finish_reason,
+    } = choice
+        .into_reply(config.model_settings.completion_style)
+        .with_context(|| format!("Response #{request_index} contains no reply content"))?;
 
-    if request_index != 1 {
-        println!(
-            "\n🤖 Assistant's response:\n\n{}\n",
-            parsed["choices"][0]["message"]["content"]
+    // Tool calls and other blocks are attested in the transcript, but only text is resent
+    if !other_parts.is_empty() {
+        warn!(
+            "Response {request_index} contains {} non-text part(s)",
+            other_parts.len()
         );
+        eprintln!(
+            "ℹ️ The response also contains {} non-text part(s), which are included in the proof:",
+            other_parts.len()
+        );
+        for part in &other_parts {
+            eprintln!("   🔧 {part}");
+        }
+    }
+
+    // A reply cut off by the token limit would otherwise be attested as if it were complete
+    if finish_reason.as_deref() == Some("length") {
+        if config.options.fail_on_truncation {
+            anyhow::bail!("Response #{request_index} was truncated by the model's token limit");
+        }
+        warn!("Response {request_index} was truncated by the model's token limit");
+        eprintln!("⚠️ The response was cut off by the model's token limit and is incomplete.");
+    }
+
+    let received_assistant_message =
+        serde_json::json!({"role": "assistant", "content": reply_content});
+    messages.push(received_assistant_message);
+
+    if !is_setup_round {
+        eprintln!("\n🤖 Assistant's response:\n\n{}\n", reply_content);
+    }
+
+    // Not every API reports the token usage, so it is only recorded when present
+    if let Some(usage) = parsed.usage {
+        if !is_setup_round {
+            eprintln!(
+                "📊 Tokens used: {} prompt, {} completion.",
+                usage.prompt_tokens, usage.completion_tokens
+            );
+        }
+        token_usage.push(usage);
     }
 
     Ok(false)
 }
 
+/// The parts of a chat or text completion response that the prover relies on
+#[derive(Debug, Deserialize)]
+struct CompletionResponse {
+    choices: Vec<Choice>,
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    /// Set by the chat completion API
+    message: Option<ChoiceMessage>,
+    /// Set by the text completion API
+    text: Option<String>,
+    finish_reason: Option<String>,
+}
+
+/// The reply of the model, as read from the first choice of a response
+#[derive(Debug, PartialEq, Eq)]
+struct Reply {
+    content: String,
+    /// Blocks of the reply that are not text, such as tool calls
+    other_parts: Vec<OtherPart>,
+    finish_reason: Option<String>,
+}
+
+impl Choice {
+    /// Reads the reply of the given completion style, or `None` if the choice holds none
+    fn into_reply(self, completion_style: CompletionStyle) -> Option<Reply> {
+        let (content, other_parts) = match completion_style {
+            CompletionStyle::Chat => {
+                let message = self.message?;
+                let tool_calls = message.tool_calls.unwrap_or_default();
+                if message.content.is_none() && tool_calls.is_empty() {
+                    return None;
+                }
+                let (text, mut other_parts) = message
+                    .content
+                    .map(MessageContent::into_text)
+                    .unwrap_or_default();
+                other_parts.extend(tool_calls.iter().map(OtherPart::from_tool_call));
+                (text, other_parts)
+            }
+            CompletionStyle::Text => (self.text?, vec![]),
+        };
+
+        Some(Reply {
+            content,
+            other_parts,
+            finish_reason: self.finish_reason,
+        })
+    }
+}
+
+/// The reply of a chat completion. Its `role` is not read, as some OpenAI-compatible servers
+/// omit it, and the reply is always recorded as the assistant's
+#[derive(Debug, Deserialize)]
+struct ChoiceMessage {
+    content: Option<MessageContent>,
+    tool_calls: Option<Vec<serde_json::Value>>,
+}
+
+/// Message content, either plain text or a list of typed blocks such as `text` and `tool_use`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Deserialize)]
+struct ContentPart {
+    #[serde(rename = "type")]
+    kind: String,
+    text: Option<String>,
+    /// Set on `tool_use` blocks, along with the `input` of the call
+    name: Option<String>,
+    input: Option<serde_json::Value>,
+}
+
+/// A block of a reply that is not text, such as a tool call, kept to be shown to the user
+#[derive(Debug, PartialEq, Eq)]
+struct OtherPart {
+    kind: String,
+    name: Option<String>,
+    /// The arguments of a tool call, as JSON
+    input: Option<String>,
+}
+
+impl OtherPart {
+    /// Reads an OpenAI style tool call, which nests its name and arguments under `function`
+    fn from_tool_call(tool_call: &serde_json::Value) -> Self {
+        let function = &tool_call["function"];
+        Self {
+            kind: tool_call["type"]
+                .as_str()
+                .unwrap_or("tool_call")
+                .to_string(),
+            name: function["name"].as_str().map(str::to_string),
+            input: function["arguments"].as_str().map(str::to_string),
+        }
+    }
+}
+
+impl std::fmt::Display for OtherPart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(name) = &self.name {
+            write!(f, " `{name}`")?;
+        }
+        if let Some(input) = &self.input {
+            write!(f, ": {input}")?;
+        }
+        Ok(())
+    }
+}
+
+impl MessageContent {
+    /// Returns the text of the content, along with the blocks that are not text
+    fn into_text(self) -> (String, Vec<OtherPart>) {
+        match self {
+            MessageContent::Text(text) => (text, vec![]),
+            MessageContent::Parts(parts) => {
+                let mut text = String::new();
+                let mut other_parts = vec![];
+                for part in parts {
+                    match part.text {
+                        Some(part_text) if part.kind == "text" => text.push_str(&part_text),
+                        _ => other_parts.push(OtherPart {
+                            kind: part.kind,
+                            name: part.name,
+                            input: part.input.map(|input| input.to_string()),
+                        }),
+                    }
+                }
+                (text, other_parts)
+            }
+        }
+    }
+}
+
+/// Token counts reported by the model API for a single exchange
+#[derive(Debug, Default, Deserialize)]
+struct TokenUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+    #[serde(default)]
+    total_tokens: u64,
+}
+
 fn generate_request(
     messages: &mut Vec<serde_json::Value>,
-    model_settings: &ModelSettings,
+    config: &Config,
 ) -> Result<hyper::Request<String>> {
-    let messages = serde_json::to_value(messages).context("Error serializing messages")?;
+    let model_settings = &config.model_settings;
+    // The setup prompt and its reply always open the conversation
+    let setup_messages = if model_settings.setup_prompt.is_some() {
+        2
+    } else {
+        0
+    };
+    let messages = history_window(messages, setup_messages, config.options.history_window);
+
     let mut json_body = serde_json::Map::new();
     json_body.insert("model".to_string(), serde_json::json!(model_settings.id));
-    json_body.insert("messages".to_string(), messages);
+    match model_settings.completion_style {
+        CompletionStyle::Chat => {
+            let messages = serde_json::to_value(&messages).context("Error serializing messages")?;
+            json_body.insert("messages".to_string(), messages);
+        }
+        CompletionStyle::Text => {
+            let prompt = build_text_prompt(&messages);
+            json_body.insert("prompt".to_string(), serde_json::json!(prompt));
+        }
+    }
     let json_body = serde_json::Value::Object(json_body);
 
     // Build the HTTP request to send the prompt to Model's API
-    hyper::Request::builder()
+    let mut request_builder = hyper::Request::builder()
         .method(Method::POST)
-        .uri(model_settings.api_settings.inference_route)
+        .uri(model_settings.inference_route())
         .header(HOST, model_settings.api_settings.server_domain)
         .header("Accept-Encoding", "identity")
         .header(CONNECTION, "keep-alive")
@@ -250,11 +772,63 @@ fn generate_request(
         .header(
             AUTHORIZATION,
             format!("Bearer {}", model_settings.api_settings.api_key),
-        )
+        );
+
+    // User provided headers are sent as part of the request, and hence are included in the proof.
+    // They replace a default header of the same name instead of being sent alongside it
+    if let Some(headers) = request_builder.headers_mut() {
+        for (name, _) in &config.options.extra_headers {
+            headers.remove(name.as_str());
+        }
+    }
+    for (name, value) in &config.options.extra_headers {
+        request_builder = request_builder.header(name.as_str(), value.as_str());
+    }
+
+    // The expiry is revealed along with the request so that verifiers can reject stale proofs
+    if let Some(expires_at) = config.proof_expires_at {
+        request_builder = request_builder.header(PROOF_EXPIRY_HEADER, expires_at.to_string());
+    }
+
+    request_builder
         .body(json_body.to_string())
         .context("Error building the request")
 }
 
+/// Selects the messages to send: the `setup_messages` opening the conversation, the last
+/// `window` exchanges and the new message. The whole history is sent when no window is set
+fn history_window(
+    messages: &[serde_json::Value],
+    setup_messages: usize,
+    window: Option<usize>,
+) -> Vec<serde_json::Value> {
+    let Some(window) = window else {
+        return messages.to_vec();
+    };
+
+    let kept_tail = 2 * window + 1;
+    if messages.len() <= setup_messages + kept_tail {
+        return messages.to_vec();
+    }
+
+    let mut windowed = messages[..setup_messages].to_vec();
+    windowed.extend_from_slice(&messages[messages.len() - kept_tail..]);
+    windowed
+}
+
+/// Flattens the conversation into a single prompt for the legacy text completions endpoint
+fn build_text_prompt(messages: &[serde_json::Value]) -> String {
+    let mut prompt = String::new();
+    for message in messages {
+        let role = message["role"].as_str().unwrap_or("user");
+        let content = message["content"].as_str().unwrap_or_default();
+        prompt.push_str(&format!("{role}: {content}\n"));
+    }
+    // Leave the assistant turn open for the model to complete
+    prompt.push_str("assistant:");
+    prompt
+}
+
 async fn shutdown_connection(
     prover_ctrl: ProverControl,
     request_sender: &mut SendRequest<String>,
@@ -266,7 +840,7 @@ async fn shutdown_connection(
     // Prepare final request to close the session
     let close_connection_request = hyper::Request::builder()
         .header(HOST, config.model_settings.api_settings.server_domain)
-        .uri(config.model_settings.api_settings.inference_route)
+        .uri(config.model_settings.inference_route())
         .header("Accept-Encoding", "identity")
         .header(CONNECTION, "close") // This will instruct the server to close the connection
         .body(String::new())
@@ -275,7 +849,11 @@ async fn shutdown_connection(
     debug!("Sending final request to Model's API...");
 
     // As this is the last request, we can defer decryption until the end.
-    prover_ctrl.defer_decryption().await.unwrap();
+    if config.notary_settings.defer_decryption {
+        prover_ctrl.defer_decryption().await.unwrap();
+    } else {
+        debug!("Decryption is not deferred, the final response is decrypted online");
+    }
 
     let response = request_sender
         .send_request(close_connection_request)
@@ -286,7 +864,7 @@ async fn shutdown_connection(
     extract_private_data(
         recv_private_data,
         response.headers(),
-        config.privacy_settings.response_topics_to_censor,
+        &config.privacy_settings.response_topics_to_censor,
     );
 
     // Collect the body
@@ -307,28 +885,418 @@ async fn shutdown_connection(
     );
 }
 
-pub fn save_proof_to_file<T: Serialize>(proof: &T, model_id: &str) -> Result<PathBuf> {
-    // Generate timestamp
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs();
+/// Names the files written by a run after the model and the time the run started, so that the
+/// proofs of a batch and their manifest share the same prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunId {
+    name: String,
+    /// UNIX timestamp at which the run started
+    started_at: u64,
+}
+
+impl RunId {
+    fn new(model_id: &str) -> Self {
+        let started_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        Self::at(model_id, started_at)
+    }
 
+    fn at(model_id: &str, started_at: u64) -> Self {
+        let sanitised_model_id = model_id.replace(" ", "_").replace("/", "_");
+        Self {
+            name: format!("{sanitised_model_id}_{started_at}"),
+            started_at,
+        }
+    }
+}
+
+pub fn save_proof_to_file<T: Serialize>(
+    proof: &T,
+    run_id: &RunId,
+    compact: bool,
+    proof_number: Option<usize>,
+) -> Result<PathBuf> {
     // Create file path
-    let sanitised_model_id = model_id.replace(" ", "_").replace("/", "_");
-    let file_path = format!(
-        "{}_{}_conversation_proof.json",
-        sanitised_model_id, timestamp
-    );
+    let file_path = match proof_number {
+        Some(number) => format!("{}_{}_conversation_proof.json", run_id.name, number),
+        None => format!("{}_conversation_proof.json", run_id.name),
+    };
     let path_buf = PathBuf::from(&file_path);
 
     // Create and write to file
     let mut file = File::create(&path_buf).context("Failed to create proof file")?;
 
-    let proof_content = serde_json::to_string_pretty(proof).context("Failed to serialize proof")?;
+    let proof_content = serialize_proof(proof, compact)?;
 
     file.write_all(proof_content.as_bytes())
         .context("Failed to write proof to file")?;
 
     Ok(path_buf)
 }
+
+/// Writes the manifest of the proofs saved by a batch run to `dir`, where the proofs live
+fn save_manifest(run_id: &RunId, dir: &Path, proof_paths: &[PathBuf]) -> Result<PathBuf> {
+    let manifest_path = dir.join(format!("{}_manifest.json", run_id.name));
+
+    Manifest::build(run_id.name.clone(), run_id.started_at, dir, proof_paths)
+        .context("Failed to build the manifest")?
+        .save(&manifest_path)?;
+
+    Ok(manifest_path)
+}
+
+/// Serializes the proof as pretty JSON for human inspection, or minified to save space
+fn serialize_proof<T: Serialize>(proof: &T, compact: bool) -> Result<String> {
+    let proof_content = if compact {
+        serde_json::to_string(proof)
+    } else {
+        serde_json::to_string_pretty(proof)
+    };
+    proof_content.context("Failed to serialize proof")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn numbered(count: usize) -> Vec<serde_json::Value> {
+        (0..count).map(|n| json!({ "content": n })).collect()
+    }
+
+    /// Parses `response` and reads the reply of its first choice
+    fn first_reply(
+        response: serde_json::Value,
+        completion_style: CompletionStyle,
+    ) -> Option<Reply> {
+        serde_json::from_value::<CompletionResponse>(response)
+            .unwrap()
+            .choices
+            .into_iter()
+            .next()?
+            .into_reply(completion_style)
+    }
+
+    #[test]
+    fn text_completion_reply_is_read_from_text() {
+        let response = json!({
+            "id": "cmpl-1",
+            "object": "text_completion",
+            "choices": [{ "index": 0, "text": " Hello there!", "finish_reason": "stop" }]
+        });
+        assert_eq!(
+            first_reply(response.clone(), CompletionStyle::Text),
+            Some(Reply {
+                content: " Hello there!".to_string(),
+                other_parts: vec![],
+                finish_reason: Some("stop".to_string()),
+            })
+        );
+        // A text completion holds no chat message
+        assert_eq!(first_reply(response, CompletionStyle::Chat), None);
+    }
+
+    #[test]
+    fn chat_reply_is_read_from_string_content() {
+        let response = json!({
+            "choices": [{
+                "message": { "role": "assistant", "content": "Hello!" },
+                "finish_reason": "stop"
+            }]
+        });
+        assert_eq!(
+            first_reply(response, CompletionStyle::Chat),
+            Some(Reply {
+                content: "Hello!".to_string(),
+                other_parts: vec![],
+                finish_reason: Some("stop".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn message_without_role_is_the_assistants_reply() {
+        let response = json!({ "choices": [{ "message": { "content": "Hi, how can I help?" } }] });
+        let reply = first_reply(response, CompletionStyle::Chat).unwrap();
+        assert_eq!(reply.content, "Hi, how can I help?");
+    }
+
+    #[test]
+    fn null_content_with_tool_calls_is_a_reply() {
+        let response = json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "get_weather", "arguments": "{}" }
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }]
+        });
+        let reply = first_reply(response, CompletionStyle::Chat).unwrap();
+        assert_eq!(reply.content, "");
+        assert_eq!(reply.other_parts.len(), 1);
+        assert_eq!(reply.other_parts[0].name.as_deref(), Some("get_weather"));
+    }
+
+    #[test]
+    fn null_content_without_tool_calls_is_no_reply() {
+        let response =
+            json!({ "choices": [{ "message": { "role": "assistant", "content": null } }] });
+        assert_eq!(first_reply(response, CompletionStyle::Chat), None);
+    }
+
+    #[test]
+    fn empty_choices_hold_no_reply() {
+        assert_eq!(
+            first_reply(json!({ "choices": [] }), CompletionStyle::Chat),
+            None
+        );
+    }
+
+    #[test]
+    fn usage_block_is_parsed() {
+        let response = serde_json::from_value::<CompletionResponse>(json!({
+            "choices": [],
+            "usage": { "prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17 }
+        }))
+        .unwrap();
+        let usage = response.usage.unwrap();
+        assert_eq!(
+            (
+                usage.prompt_tokens,
+                usage.completion_tokens,
+                usage.total_tokens
+            ),
+            (12, 5, 17)
+        );
+    }
+
+    #[test]
+    fn malformed_responses_are_rejected() {
+        for payload in [
+            "",
+            "not json",
+            r#"{ "error": { "message": "Invalid API key" } }"#,
+            r#"{ "choices": "none" }"#,
+        ] {
+            assert!(
+                serde_json::from_str::<CompletionResponse>(payload).is_err(),
+                "{payload}"
+            );
+        }
+    }
+
+    #[test]
+    fn text_prompt_flattens_the_conversation() {
+        let messages = vec![
+            json!({ "role": "system", "content": "Be brief." }),
+            json!({ "role": "user", "content": "Hi" }),
+            json!({ "role": "assistant", "content": "Hello" }),
+            json!({ "content": "Bye" }),
+        ];
+        assert_eq!(
+            build_text_prompt(&messages),
+            "system: Be brief.\nuser: Hi\nassistant: Hello\nuser: Bye\nassistant:"
+        );
+    }
+
+    #[test]
+    fn tool_use_blocks_keep_their_name_and_input() {
+        let content = serde_json::from_value::<MessageContent>(json!([
+            { "type": "text", "text": "Let me check." },
+            { "type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": { "city": "Paris" } },
+        ]))
+        .unwrap();
+
+        let (text, other_parts) = content.into_text();
+        assert_eq!(text, "Let me check.");
+        assert_eq!(
+            other_parts,
+            vec![OtherPart {
+                kind: "tool_use".to_string(),
+                name: Some("get_weather".to_string()),
+                input: Some(r#"{"city":"Paris"}"#.to_string()),
+            }]
+        );
+        assert_eq!(
+            other_parts[0].to_string(),
+            r#"tool_use `get_weather`: {"city":"Paris"}"#
+        );
+    }
+
+    #[test]
+    fn tool_calls_keep_their_name_and_arguments() {
+        let tool_call = json!({
+            "id": "call_1",
+            "type": "function",
+            "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" }
+        });
+        assert_eq!(
+            OtherPart::from_tool_call(&tool_call).to_string(),
+            r#"function `get_weather`: {"city":"Paris"}"#
+        );
+    }
+
+    /// A fresh directory for the files written by a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "passport-attribution-{}-{name}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes a dummy proof for every prompt, failing on the prompt `fail_on` if given
+    struct StubAttester {
+        dir: PathBuf,
+        fail_on: Option<usize>,
+        attested: Vec<String>,
+    }
+
+    impl AttestPrompt for StubAttester {
+        async fn attest_prompt(
+            &mut self,
+            run_id: &RunId,
+            number: usize,
+            prompt: &str,
+        ) -> Result<Option<PathBuf>> {
+            if self.fail_on == Some(number) {
+                anyhow::bail!("the notary closed the connection");
+            }
+            self.attested.push(prompt.to_string());
+            let proof_path =
+                PathBuf::from(format!("{}_{number}_conversation_proof.json", run_id.name));
+            std::fs::write(self.dir.join(&proof_path), prompt).unwrap();
+            Ok(Some(proof_path))
+        }
+    }
+
+    fn three_prompts() -> Vec<String> {
+        ["One", "Two", "Three"].map(str::to_string).to_vec()
+    }
+
+    #[tokio::test]
+    async fn batch_of_three_prompts_writes_a_manifest_of_three_proofs() {
+        let dir = test_dir("batch");
+        let run_id = RunId::at("gpt-4o", 1731664270);
+        let mut attester = StubAttester {
+            dir: dir.clone(),
+            fail_on: None,
+            attested: vec![],
+        };
+
+        attest_batch(&mut attester, &three_prompts(), &run_id, &dir)
+            .await
+            .unwrap();
+        assert_eq!(attester.attested, three_prompts());
+
+        let manifest =
+            crate::remote::verify_manifest(&dir.join("gpt-4o_1731664270_manifest.json")).unwrap();
+        assert_eq!(manifest.run_id, "gpt-4o_1731664270");
+        assert_eq!(
+            manifest
+                .files
+                .iter()
+                .map(|entry| entry.path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>(),
+            (1..=3)
+                .map(|n| format!("gpt-4o_1731664270_{n}_conversation_proof.json"))
+                .collect::<Vec<_>>()
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_prompt_keeps_the_manifest_of_the_saved_proofs() {
+        let dir = test_dir("failed-batch");
+        let run_id = RunId::at("gpt-4o", 1731664270);
+        let mut attester = StubAttester {
+            dir: dir.clone(),
+            fail_on: Some(3),
+            attested: vec![],
+        };
+
+        let err = attest_batch(&mut attester, &three_prompts(), &run_id, &dir)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("prompt #3"), "{err:#}");
+
+        let manifest =
+            crate::remote::verify_manifest(&dir.join("gpt-4o_1731664270_manifest.json")).unwrap();
+        assert_eq!(manifest.files.len(), 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prompts_are_read_one_per_non_empty_line() {
+        let dir = test_dir("prompts");
+        let path = dir.join("prompts.txt");
+        std::fs::write(
+            &path,
+            "  What is 2 + 2?\n\n\tName a colour.  \r\n   \nBye\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_prompts(&path).unwrap(),
+            vec!["What is 2 + 2?", "Name a colour.", "Bye"]
+        );
+
+        std::fs::write(&path, "\n   \n").unwrap();
+        assert!(read_prompts(&path).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn run_id_is_named_after_the_model_and_start_time() {
+        let run_id = RunId::at("anthropic/claude 3", 1731664270);
+        assert_eq!(run_id.name, "anthropic_claude_3_1731664270");
+        assert_eq!(run_id.started_at, 1731664270);
+    }
+
+    #[test]
+    fn history_window_keeps_short_conversations() {
+        let messages = numbered(5);
+        assert_eq!(history_window(&messages, 0, Some(2)), messages);
+        assert_eq!(history_window(&messages, 2, None), messages);
+    }
+
+    #[test]
+    fn history_window_drops_the_oldest_exchanges() {
+        let messages = numbered(9);
+        assert_eq!(
+            history_window(&messages, 0, Some(1)),
+            vec![
+                messages[6].clone(),
+                messages[7].clone(),
+                messages[8].clone()
+            ]
+        );
+    }
+
+    #[test]
+    fn history_window_keeps_the_setup_prefix() {
+        let messages = numbered(9);
+        assert_eq!(
+            history_window(&messages, 2, Some(1)),
+            vec![
+                messages[0].clone(),
+                messages[1].clone(),
+                messages[6].clone(),
+                messages[7].clone(),
+                messages[8].clone()
+            ]
+        );
+    }
+}
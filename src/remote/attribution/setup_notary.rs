@@ -1,4 +1,5 @@
 use crate::remote::attribution::config::Config;
+use crate::remote::attribution::tlsn_operations::with_notary_hint;
 use anyhow::{Context, Result};
 use futures::{AsyncRead, AsyncWrite};
 use hyper::client::conn::http1::SendRequest;
@@ -6,7 +7,13 @@ use hyper_util::rt::TokioIo;
 use notary_client::{Accepted, NotarizationRequest, NotaryClient};
 use p256::pkcs8::DecodePrivateKey;
 
+use std::io;
+use std::pin::Pin;
 use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
 use tlsn_core::SessionHeader;
 use tlsn_prover::tls::state::Closed;
 use tlsn_prover::tls::{Prover, ProverConfig, ProverControl, ProverError};
@@ -16,16 +23,112 @@ use tokio_util::compat::FuturesAsyncReadCompatExt;
 use tokio_util::compat::TokioAsyncReadCompatExt;
 use tracing::{debug, warn};
 
+/// Time allowed to connect to the model's API server, including the TLS handshake
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Returns the time allowed to connect to the model's API server. The MPC TLS handshake only
+/// runs once the first request is written, so the first response has to arrive within it too
+pub(super) fn connect_timeout(config: &Config) -> Duration {
+    config
+        .options
+        .connect_timeout
+        .unwrap_or(DEFAULT_CONNECT_TIMEOUT)
+}
+
+/// Records whether the Notary closed its connection, which tlsn only reports as an opaque MPC or
+/// I/O error
+#[derive(Debug, Clone, Default)]
+pub(super) struct NotaryClosed(Arc<AtomicBool>);
+
+impl NotaryClosed {
+    pub(super) fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Wraps the connection to the Notary, so that it is recorded when the Notary closes it
+    fn watch<T>(&self, conn: T) -> WatchClosed<T> {
+        WatchClosed {
+            inner: conn,
+            closed: self.clone(),
+        }
+    }
+}
+
+/// A connection to the Notary that records it in `closed` when the Notary hangs up
+struct WatchClosed<T> {
+    inner: T,
+    closed: NotaryClosed,
+}
+
+impl<T> WatchClosed<T> {
+    fn record<R>(&self, result: Poll<io::Result<R>>) -> Poll<io::Result<R>> {
+        if let Poll::Ready(Err(err)) = &result {
+            if matches!(
+                err.kind(),
+                io::ErrorKind::UnexpectedEof
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+            ) {
+                self.closed.0.store(true, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for WatchClosed<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        // Reading nothing into a non-empty buffer means the Notary closed the connection
+        if matches!(result, Poll::Ready(Ok(0))) && !buf.is_empty() {
+            this.closed.0.store(true, Ordering::Relaxed);
+        }
+        this.record(result)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for WatchClosed<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        this.record(result)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_flush(cx);
+        this.record(result)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_close(cx);
+        this.record(result)
+    }
+}
+
 pub(super) async fn setup_connections(
     config: &Config,
 ) -> Result<(
     ProverControl,
     JoinHandle<Result<Prover<Closed>, ProverError>>,
     SendRequest<String>,
+    NotaryClosed,
 )> {
+    let notary_closed = NotaryClosed::default();
     let prover = if cfg!(feature = "dummy-notary") {
-        println!("🚨 WARNING: Running in a test mode.");
-        println!("🚨 WARNING: Authenticating output with a local dummy notary, which is not secure and should not be used in production.");
+        eprintln!("🚨 WARNING: Running in a test mode.");
+        eprintln!("🚨 WARNING: Authenticating output with a local dummy notary, which is not secure and should not be used in production.");
         let (prover_socket, notary_socket) = tokio::io::duplex(1 << 16);
 
         let connection_id = format!("{}_conversation", config.model_settings.id);
@@ -46,9 +149,9 @@ pub(super) async fn setup_connections(
         // Create a Prover and set it up with the Notary
         // This will set up the MPC backend prior to connecting to the server.
         Prover::new(prover_config)
-            .setup(prover_socket.compat())
+            .setup(notary_closed.watch(prover_socket.compat()))
             .await
-            .context("Error setting up prover")?
+            .map_err(|e| with_notary_hint(e.into(), "Error setting up prover", &notary_closed))?
     } else {
         // Build a client to connect to the notary server.
         let notary_client = NotaryClient::builder()
@@ -82,23 +185,39 @@ pub(super) async fn setup_connections(
 
         // Create a new prover and set up the MPC backend.
         Prover::new(prover_config)
-            .setup(notary_connection.compat())
+            .setup(notary_closed.watch(notary_connection.compat()))
             .await
-            .context("Error setting up prover")?
+            .map_err(|e| with_notary_hint(e.into(), "Error setting up prover", &notary_closed))?
     };
 
     debug!("Prover setup complete!");
-    // Open a new socket to the application server.
-    let client_socket =
-        tokio::net::TcpStream::connect((config.model_settings.api_settings.server_domain, 443))
+
+    let server_domain = config.model_settings.api_settings.server_domain;
+    let connect_timeout = connect_timeout(config);
+    let deadline = tokio::time::Instant::now() + connect_timeout;
+    let timed_out = || {
+        anyhow::anyhow!(
+            "Timed out after {}s while connecting to `{}`",
+            connect_timeout.as_secs(),
+            server_domain
+        )
+    };
+
+    // An unresponsive server would otherwise stall the setup indefinitely
+    let (tls_connection, prover_fut) = tokio::time::timeout_at(deadline, async move {
+        // Open a new socket to the application server.
+        let client_socket = tokio::net::TcpStream::connect((server_domain, 443))
             .await
             .context("Error connecting to server")?;
 
-    // Bind the Prover to server connection
-    let (tls_connection, prover_fut) = prover
-        .connect(client_socket.compat())
-        .await
-        .context("Error connecting Prover to server")?;
+        // Bind the Prover to server connection
+        prover
+            .connect(client_socket.compat())
+            .await
+            .context("Error connecting Prover to server")
+    })
+    .await
+    .map_err(|_| timed_out())??;
     let tls_connection = TokioIo::new(tls_connection.compat());
 
     warn!("Test");
@@ -113,9 +232,13 @@ pub(super) async fn setup_connections(
     warn!("Test 2");
 
     // Attach the hyper HTTP client to the TLS connection
-    let (request_sender, connection) = hyper::client::conn::http1::handshake(tls_connection)
-        .await
-        .context("Error establishing HTTP connection")?;
+    let (request_sender, connection) = tokio::time::timeout_at(
+        deadline,
+        hyper::client::conn::http1::handshake(tls_connection),
+    )
+    .await
+    .map_err(|_| timed_out())?
+    .context("Error establishing HTTP connection")?;
 
     warn!("Test 4");
 
@@ -124,7 +247,7 @@ pub(super) async fn setup_connections(
 
     warn!("Test 3");
 
-    Ok((prover_ctrl, prover_task, request_sender))
+    Ok((prover_ctrl, prover_task, request_sender, notary_closed))
 }
 
 /// Runs a simple Notary with the provided connection to the Prover.
@@ -150,3 +273,34 @@ pub async fn run_dummy_notary<T: AsyncWrite + AsyncRead + Send + Unpin + 'static
         .await
         .context("Error running dummy notary")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn notary_closing_the_connection_is_reported() {
+        let (prover_socket, notary_socket) = tokio::io::duplex(1 << 16);
+        // The Notary hangs up before the session is set up
+        drop(notary_socket);
+
+        let notary_closed = NotaryClosed::default();
+        let prover_config = ProverConfig::builder()
+            .id("closing_notary")
+            .server_dns("api.example.com")
+            .build()
+            .unwrap();
+        let setup = Prover::new(prover_config).setup(notary_closed.watch(prover_socket.compat()));
+        let err = tokio::time::timeout(Duration::from_secs(30), setup)
+            .await
+            .expect("the setup should fail once the notary is gone")
+            .unwrap_err();
+
+        assert!(notary_closed.get());
+        let err = with_notary_hint(err.into(), "Error setting up prover", &notary_closed);
+        assert!(
+            format!("{err:#}").contains("the notary closed the connection"),
+            "{err:#}"
+        );
+    }
+}
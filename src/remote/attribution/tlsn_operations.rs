@@ -1,43 +1,72 @@
+use crate::remote::attribution::config::RevealPolicy;
+use crate::remote::attribution::setup_notary::NotaryClosed;
 use anyhow::Context;
+use hyper::header::HeaderValue;
 use hyper::HeaderMap;
 use std::ops::Range;
+use std::path::Path;
 use tlsn_core::commitment::CommitmentId;
 use tlsn_core::proof::TlsProof;
 use tlsn_core::NotarizedSession;
 use tlsn_prover::tls::state::Closed;
 use tlsn_prover::tls::{Prover, ProverError};
 use tokio::task::JoinHandle;
-use tracing::debug;
+use tracing::{debug, warn};
 
 pub(super) async fn notarise_session(
     prover_task: JoinHandle<anyhow::Result<Prover<Closed>, ProverError>>,
     recv_private_data: &[Vec<u8>],
     sent_private_data: &[Vec<u8>],
+    dump_transcript_dir: Option<&Path>,
+    notary_closed: &NotaryClosed,
 ) -> anyhow::Result<(Vec<CommitmentId>, Vec<CommitmentId>, NotarizedSession)> {
     // The Prover task should be done now, so we can grab it.
     let prover = prover_task
         .await
-        .context("Error waiting for prover task")??;
+        .context("Error waiting for prover task")?
+        .map_err(|e| with_notary_hint(e.into(), "Prover task failed", notary_closed))?;
 
     // Prepare for notarization
     let mut prover = prover.start_notarize();
 
+    if let Some(dir) = dump_transcript_dir {
+        dump_transcript(
+            dir,
+            prover.sent_transcript().data(),
+            prover.recv_transcript().data(),
+        )
+        .context("Error dumping the transcript")?;
+    }
+
+    let sent_private_data = sent_private_data
+        .iter()
+        .map(|v| v.as_slice())
+        .collect::<Vec<&[u8]>>();
+    let recv_private_data = recv_private_data
+        .iter()
+        .map(|v| v.as_slice())
+        .collect::<Vec<&[u8]>>();
+
     // Notarize the session
-    let (public_sent_commitment_ids, _) = find_ranges(
-        prover.sent_transcript().data(),
-        &sent_private_data
-            .iter()
-            .map(|v| v.as_slice())
-            .collect::<Vec<&[u8]>>(),
-    );
+    let (public_sent_commitment_ids, _) =
+        find_ranges(prover.sent_transcript().data(), &sent_private_data);
+
+    let (public_received_commitment_ids, _) =
+        find_ranges(prover.recv_transcript().data(), &recv_private_data);
 
-    let (public_received_commitment_ids, _) = find_ranges(
+    // Make sure that none of the censored values made it into the revealed ranges
+    ensure_redacted(
+        prover.sent_transcript().data(),
+        &public_sent_commitment_ids,
+        &sent_private_data,
+    )
+    .context("Sent transcript is not fully redacted")?;
+    ensure_redacted(
         prover.recv_transcript().data(),
-        &recv_private_data
-            .iter()
-            .map(|v| v.as_slice())
-            .collect::<Vec<&[u8]>>(),
-    );
+        &public_received_commitment_ids,
+        &recv_private_data,
+    )
+    .context("Received transcript is not fully redacted")?;
 
     let builder = prover.commitment_builder();
 
@@ -55,7 +84,7 @@ pub(super) async fn notarise_session(
     let notarized_session = prover
         .finalize()
         .await
-        .context("Error finalizing notarization")?;
+        .map_err(|e| with_notary_hint(e.into(), "Error finalizing notarization", notary_closed))?;
 
     debug!("Notarization complete!");
 
@@ -66,22 +95,87 @@ pub(super) async fn notarise_session(
     ))
 }
 
+/// Adds `context` to `err`, and if the notary closed the connection mid-session,
+/// explains that in place of the opaque MPC error and hints at how to recover
+pub(super) fn with_notary_hint(
+    err: anyhow::Error,
+    context: &'static str,
+    notary_closed: &NotaryClosed,
+) -> anyhow::Error {
+    if notary_closed.get() || notary_closed_early(&err) {
+        err.context(format!(
+            "{context}: the notary closed the connection before the session was notarized. \
+             It may have hit its session timeout or be overloaded; try again, or keep the \
+             conversation shorter"
+        ))
+    } else {
+        err.context(context)
+    }
+}
+
+/// Whether the error comes from the peer closing the connection mid-session. The I/O error is
+/// wrapped transparently by `ProverError`, so it never appears as a cause of its own and has to be
+/// matched on the variant
+fn notary_closed_early(err: &anyhow::Error) -> bool {
+    let closed = |io: &std::io::Error| {
+        matches!(
+            io.kind(),
+            std::io::ErrorKind::UnexpectedEof
+                | std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::ConnectionAborted
+                | std::io::ErrorKind::BrokenPipe
+        )
+    };
+
+    err.chain().any(|cause| {
+        if let Some(prover_err) = cause.downcast_ref::<ProverError>() {
+            matches!(prover_err, ProverError::IOError(io) if closed(io))
+        } else {
+            cause.downcast_ref::<std::io::Error>().is_some_and(closed)
+        }
+    })
+}
+
+/// Writes the raw, uncensored transcripts to `dir` for debugging
+fn dump_transcript(dir: &Path, sent: &[u8], recv: &[u8]) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir).context("Failed to create the transcript directory")?;
+
+    let sent_path = dir.join("sent_transcript.raw");
+    let recv_path = dir.join("recv_transcript.raw");
+    std::fs::write(&sent_path, sent).context("Failed to write the sent transcript")?;
+    std::fs::write(&recv_path, recv).context("Failed to write the received transcript")?;
+
+    warn!(
+        "The raw transcripts were written to `{}` and `{}`. They are NOT censored and contain secrets such as your API key, do not share them.",
+        sent_path.display(),
+        recv_path.display()
+    );
+
+    Ok(())
+}
+
 pub(super) fn build_proof(
     (sent_commitment_ids, received_commitment_ids, notarized_session): (
         Vec<CommitmentId>,
         Vec<CommitmentId>,
         NotarizedSession,
     ),
+    reveal_policy: RevealPolicy,
 ) -> TlsProof {
     let session_proof = notarized_session.session_proof();
 
     let mut proof_builder = notarized_session.data().build_substrings_proof();
 
-    for id in sent_commitment_ids {
-        proof_builder.reveal_by_id(id).unwrap();
+    // Data that is not revealed stays committed in the session, but is hidden from the verifier
+    if reveal_policy.reveals_request() {
+        for id in sent_commitment_ids {
+            proof_builder.reveal_by_id(id).unwrap();
+        }
     }
-    for id in received_commitment_ids {
-        proof_builder.reveal_by_id(id).unwrap();
+    if reveal_policy.reveals_response() {
+        for id in received_commitment_ids {
+            proof_builder.reveal_by_id(id).unwrap();
+        }
     }
 
     let substrings_proof = proof_builder.build().unwrap();
@@ -100,16 +194,28 @@ pub(super) fn extract_private_data(
     for (header_name, header_value) in headers {
         if topics_to_censor.contains(&header_name.as_str()) {
             let header_value = header_value.as_bytes().to_vec();
-            if !recv_private_data.contains(&header_value) {
+            // An empty value has nothing to censor
+            if !header_value.is_empty() && !recv_private_data.contains(&header_value) {
                 recv_private_data.push(header_value);
             }
         }
     }
 }
 
+/// Returns a copy of `headers` with the values of the censored headers masked, for logging
+pub(super) fn redacted_headers(headers: &HeaderMap, topics_to_censor: &[&str]) -> HeaderMap {
+    let mut headers = headers.clone();
+    for (header_name, header_value) in headers.iter_mut() {
+        if topics_to_censor.contains(&header_name.as_str()) {
+            *header_value = HeaderValue::from_static("[redacted]");
+        }
+    }
+    headers
+}
+
 fn find_ranges(seq: &[u8], sub_seq: &[&[u8]]) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
     let mut private_ranges = Vec::new();
-    for s in sub_seq {
+    for s in sub_seq.iter().filter(|s| !s.is_empty()) {
         for (idx, w) in seq.windows(s.len()).enumerate() {
             if w == *s {
                 private_ranges.push(idx..(idx + w.len()));
@@ -126,7 +232,8 @@ fn find_ranges(seq: &[u8], sub_seq: &[&[u8]]) -> (Vec<Range<usize>>, Vec<Range<u
         if r.start > last_end {
             public_ranges.push(last_end..r.start);
         }
-        last_end = r.end;
+        // Ranges may overlap, so never move the end back into an already private range
+        last_end = last_end.max(r.end);
     }
 
     if last_end < seq.len() {
@@ -135,3 +242,113 @@ fn find_ranges(seq: &[u8], sub_seq: &[&[u8]]) -> (Vec<Range<usize>>, Vec<Range<u
 
     (public_ranges, private_ranges)
 }
+
+/// Checks that no censored value appears anywhere within the ranges that will be revealed
+fn ensure_redacted(
+    seq: &[u8],
+    public_ranges: &[Range<usize>],
+    sub_seq: &[&[u8]],
+) -> anyhow::Result<()> {
+    for range in public_ranges {
+        let revealed = &seq[range.clone()];
+        for s in sub_seq.iter().filter(|s| !s.is_empty()) {
+            if revealed.windows(s.len()).any(|w| w == *s) {
+                anyhow::bail!(
+                    "A censored value would be revealed within the range {:?}",
+                    range
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notary_closing_the_connection_is_detected() {
+        let closed = ProverError::IOError(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "notary closed the connection",
+        ));
+        assert!(notary_closed_early(&anyhow::Error::from(closed)));
+
+        let hinted = with_notary_hint(
+            ProverError::IOError(std::io::ErrorKind::ConnectionReset.into()).into(),
+            "Error finalizing notarization",
+            &NotaryClosed::default(),
+        );
+        assert!(hinted
+            .to_string()
+            .contains("the notary closed the connection"));
+    }
+
+    #[test]
+    fn other_prover_errors_get_no_hint() {
+        let refused = ProverError::IOError(std::io::ErrorKind::PermissionDenied.into());
+        assert!(!notary_closed_early(&anyhow::Error::from(refused)));
+    }
+
+    #[test]
+    fn redacted_headers_masks_only_censored_values() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("secret"));
+        headers.insert("cf-ray", HeaderValue::from_static("8f2a"));
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+
+        let logged = redacted_headers(&headers, &["x-api-key", "cf-ray"]);
+        assert_eq!(logged["x-api-key"], "[redacted]");
+        assert_eq!(logged["cf-ray"], "[redacted]");
+        assert_eq!(logged["content-type"], "application/json");
+    }
+
+    fn redact(seq: &[u8], private: &[&[u8]]) -> Vec<u8> {
+        let (public_ranges, _) = find_ranges(seq, private);
+        ensure_redacted(seq, &public_ranges, private).unwrap();
+
+        let mut redacted = vec![b'X'; seq.len()];
+        for range in public_ranges {
+            redacted[range.clone()].copy_from_slice(&seq[range]);
+        }
+        redacted
+    }
+
+    #[test]
+    fn token_in_header_and_body_is_fully_redacted() {
+        let seq = b"authorization: Bearer sk-secret\r\n\r\n{\"echo\":\"sk-secret\"}";
+        let redacted = redact(seq, &[b"Bearer sk-secret".as_slice(), b"sk-secret"]);
+
+        assert!(!redacted.windows(9).any(|w| w == b"sk-secret"));
+        assert_eq!(
+            redacted,
+            b"authorization: XXXXXXXXXXXXXXXX\r\n\r\n{\"echo\":\"XXXXXXXXX\"}".to_vec()
+        );
+    }
+
+    #[test]
+    fn overlapping_private_ranges_are_not_revealed() {
+        // `bc` lies within `abcd` and must not move the end of the private range back
+        let seq = b"xxabcdxx";
+        let (public_ranges, _) = find_ranges(seq, &[b"abcd".as_slice(), b"bc"]);
+
+        assert_eq!(public_ranges, vec![0..2, 6..8]);
+    }
+
+    #[test]
+    fn empty_private_value_is_ignored() {
+        let seq = b"report-to: \r\n";
+        let (public_ranges, private_ranges) = find_ranges(seq, &[b"".as_slice()]);
+
+        assert_eq!(public_ranges, vec![0..seq.len()]);
+        assert!(private_ranges.is_empty());
+    }
+
+    #[test]
+    fn ensure_redacted_rejects_revealed_secret() {
+        let seq = b"secret";
+        assert!(ensure_redacted(seq, &[0..seq.len()], &[b"secret".as_slice()]).is_err());
+    }
+}
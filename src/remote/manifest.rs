@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// The kind of the proof files written by a conversation
+const CONVERSATION_PROOF_KIND: &str = "conversation_proof";
+
+/// Index of the proof files written by a batch run, so that a verifier can check that the set is
+/// complete and unmodified.
+///
+/// The manifest itself is not signed: the Prover holds no signing key, and the Notary only signs
+/// the sessions, i.e. each proof file on its own.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub run_id: String,
+    /// UNIX timestamp at which the run started
+    pub created_at: u64,
+    pub files: Vec<ManifestEntry>,
+}
+
+/// A file listed in a manifest, with its path relative to the manifest's directory
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    /// Hex encoded SHA-256 hash of the file contents
+    pub sha256: String,
+    pub kind: String,
+}
+
+impl Manifest {
+    /// Hashes the conversation proofs at `proof_paths`, which live in `dir`
+    pub(crate) fn build(
+        run_id: String,
+        created_at: u64,
+        dir: &Path,
+        proof_paths: &[PathBuf],
+    ) -> Result<Self> {
+        let files = proof_paths
+            .iter()
+            .map(|path| {
+                Ok(ManifestEntry {
+                    path: path.clone(),
+                    sha256: hash_file(&dir.join(path))?,
+                    kind: CONVERSATION_PROOF_KIND.to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            run_id,
+            created_at,
+            files,
+        })
+    }
+
+    /// Writes the manifest as pretty JSON to `path`
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        let content =
+            serde_json::to_string_pretty(self).context("Failed to serialize the manifest")?;
+        std::fs::write(path, content).context("Failed to write the manifest")
+    }
+}
+
+/// Checks that every file listed in the manifest at `manifest_path` is present and unmodified
+pub fn verify_manifest(manifest_path: &Path) -> Result<Manifest, Box<dyn std::error::Error>> {
+    let manifest = std::fs::read_to_string(manifest_path).map_err(|e| {
+        format!(
+            "Failed to read manifest at {}: {}",
+            manifest_path.display(),
+            e
+        )
+    })?;
+    let manifest: Manifest =
+        serde_json::from_str(&manifest).map_err(|e| format!("Failed to parse manifest: {}", e))?;
+
+    // The listed paths are relative to the manifest, wherever it was moved to
+    let dir = manifest_path.parent().unwrap_or(Path::new(""));
+    for entry in &manifest.files {
+        let hash = hash_file(&dir.join(&entry.path))?;
+        if hash != entry.sha256 {
+            Err(format!(
+                "`{}` was modified after the manifest was written",
+                entry.path.display()
+            ))?;
+        }
+    }
+
+    Ok(manifest)
+}
+
+fn hash_file(path: &Path) -> Result<String> {
+    let content =
+        std::fs::read(path).with_context(|| format!("Failed to read `{}`", path.display()))?;
+    Ok(hex::encode(Sha256::digest(content)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes two proof files to a fresh directory and a manifest listing them
+    fn write_run(name: &str) -> (PathBuf, PathBuf) {
+        let dir =
+            std::env::temp_dir().join(format!("passport-manifest-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let proofs = vec![PathBuf::from("proof_1.json"), PathBuf::from("proof_2.json")];
+        for (index, proof) in proofs.iter().enumerate() {
+            std::fs::write(dir.join(proof), format!("{{\"proof\": {index}}}")).unwrap();
+        }
+
+        let manifest_path = dir.join("manifest.json");
+        Manifest::build("run".to_string(), 1731664270, &dir, &proofs)
+            .unwrap()
+            .save(&manifest_path)
+            .unwrap();
+
+        (dir, manifest_path)
+    }
+
+    #[test]
+    fn unmodified_run_is_verified() {
+        let (dir, manifest_path) = write_run("unmodified");
+
+        let manifest = verify_manifest(&manifest_path).unwrap();
+        assert_eq!(manifest.files.len(), 2);
+        assert!(manifest
+            .files
+            .iter()
+            .all(|entry| entry.kind == CONVERSATION_PROOF_KIND));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn modified_file_is_detected() {
+        let (dir, manifest_path) = write_run("modified");
+        std::fs::write(dir.join("proof_2.json"), "{\"proof\": 3}").unwrap();
+
+        let err = verify_manifest(&manifest_path).unwrap_err();
+        assert!(err.to_string().contains("proof_2.json"), "{err}");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_file_is_detected() {
+        let (dir, manifest_path) = write_run("missing");
+        std::fs::remove_file(dir.join("proof_1.json")).unwrap();
+
+        assert!(verify_manifest(&manifest_path).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,5 +1,15 @@
 mod attribution;
+mod manifest;
 mod verify_attribution;
 
-pub use attribution::generate_conversation_attribution;
-pub use verify_attribution::verify_attribution;
+pub use attribution::{
+    generate_conversation_attribution, list_models, CompletionStyle, ConfigFile,
+    ConversationOptions, OptionSources, RevealPolicy, ValueSource,
+};
+pub use manifest::{verify_manifest, Manifest, ManifestEntry};
+pub use verify_attribution::{
+    check_conversation_order, parse_requests, verify_attribution, RequestView,
+};
+
+/// Header carrying the UNIX timestamp after which a proof should no longer be accepted
+const PROOF_EXPIRY_HEADER: &str = "x-proof-expires-at";
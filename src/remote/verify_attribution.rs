@@ -1,23 +1,180 @@
+use crate::remote::PROOF_EXPIRY_HEADER;
 use p256::pkcs8::DecodePublicKey;
+use std::ops::Range;
+use std::path::PathBuf;
 use std::{str, time::Duration};
 
 use tlsn_core::proof::{SessionProof, TlsProof};
+use tlsn_core::transcript::RedactedTranscript;
+use tlsn_core::ServerName;
+
+/// The request line, host and body of an HTTP request revealed in the sent transcript
+#[derive(Debug, PartialEq, Eq)]
+pub struct RequestView {
+    pub method: String,
+    pub path: String,
+    pub host: Option<String>,
+    pub body: String,
+}
+
+/// The verified contents of a proof
+pub(crate) struct VerifiedProof {
+    server_name: String,
+    /// The time at which the session was recorded
+    time: chrono::DateTime<chrono::Utc>,
+    /// Which of the trusted Notary keys signed the session
+    verifying_key: String,
+    expires_at: Option<u64>,
+    /// The revealed requests, or `None` if the Prover chose not to disclose them
+    requests: Option<Vec<RequestView>>,
+    sent: String,
+    recv: String,
+    sent_redaction: RedactionStats,
+    recv_redaction: RedactionStats,
+}
+
+/// Share of a transcript above which the proof is flagged as mostly undisclosed
+const HEAVY_REDACTION_RATIO: f64 = 0.9;
+
+/// How much of a transcript the Prover chose not to disclose
+#[derive(Debug)]
+struct RedactionStats {
+    total_bytes: usize,
+    redacted_bytes: usize,
+    redacted_ranges: usize,
+    /// Names of the headers whose values were fully redacted
+    redacted_headers: Vec<String>,
+}
+
+impl RedactionStats {
+    fn of(transcript: &RedactedTranscript) -> Self {
+        Self::new(transcript.data().len(), transcript.redacted().iter_ranges())
+    }
+
+    /// Counts the bytes in the disjoint `redacted` ranges of a transcript of `total_bytes`
+    fn new(total_bytes: usize, redacted: impl Iterator<Item = Range<usize>>) -> Self {
+        let (redacted_bytes, redacted_ranges) = redacted.fold((0, 0), |(bytes, ranges), range| {
+            (bytes + range.len(), ranges + 1)
+        });
+        Self {
+            total_bytes,
+            redacted_bytes,
+            redacted_ranges,
+            redacted_headers: Vec::new(),
+        }
+    }
+
+    fn redacted_ratio(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 0.0;
+        }
+        self.redacted_bytes as f64 / self.total_bytes as f64
+    }
+
+    fn print(&self, transcript: &str) {
+        println!(
+            "{transcript}: {} of {} bytes redacted ({:.1}%) across {} range(s).",
+            self.redacted_bytes,
+            self.total_bytes,
+            self.redacted_ratio() * 100.0,
+            self.redacted_ranges
+        );
+        if !self.redacted_headers.is_empty() {
+            println!("  Redacted headers: {}", self.redacted_headers.join(", "));
+        }
+        if self.redacted_ratio() > HEAVY_REDACTION_RATIO {
+            println!(
+                "🚨 WARNING: most of the {} transcript is redacted, the proof discloses little of it.",
+                transcript.to_lowercase()
+            );
+        }
+    }
+}
 
 /// A simple verifier which reads a proof generated by `simple_prover.rs` from "proof.json", verifies
 /// it and prints the verified data to the console.
-pub fn verify_attribution(proof_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    #[cfg(not(feature = "dummy-notary"))]
-    Err("Currently unsupported without the dummy-notary feature. Enable the dummy-notary feature to run this example."
-        .to_string())?;
+///
+/// The proof is accepted if it was signed by any of the Notary public keys at `notary_pubkey_paths`.
+/// When none are provided, the bundled dummy Notary key is trusted instead.
+pub fn verify_attribution(
+    proof_path: &str,
+    notary_pubkey_paths: &[PathBuf],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let VerifiedProof {
+        server_name,
+        time,
+        verifying_key,
+        expires_at,
+        requests,
+        sent,
+        recv,
+        sent_redaction,
+        recv_redaction,
+    } = verify_proof_file(proof_path, notary_pubkey_paths)?;
+
+    println!("-------------------------------------------------------------------");
+    println!(
+        "Successfully verified that the bytes below came from a session with {:?} at {}.",
+        server_name, time
+    );
+    println!("The session was signed by the trusted Notary key `{verifying_key}`.");
+    if let Some(expires_at) = expires_at {
+        println!(
+            "The proof is valid until {}.",
+            chrono::DateTime::UNIX_EPOCH + Duration::from_secs(expires_at)
+        );
+    }
+    match &requests {
+        Some(requests) => {
+            println!("The proof contains {} request(s):", requests.len());
+            for request in requests {
+                println!("  {} {}{}", request.method, server_name, request.path);
+            }
+        }
+        None => println!("The requests sent were not disclosed."),
+    }
+    sent_redaction.print("Sent");
+    recv_redaction.print("Received");
+    println!("Note that the bytes which the Prover chose not to disclose are shown as X.");
+    println!();
+    println!("Messages sent:");
+    println!();
+    println!("{}", sent);
+    println!();
+    println!("Messages received:");
+    println!();
+    println!("{}", recv);
+    println!("-------------------------------------------------------------------");
 
-    println!("🚨 WARNING: Verifying a proof generated by a dummy notary.");
-    println!("🚨 WARNING: Dummy notary is used for testing purposes only. It is not secure and should not be used in production.");
+    Ok(())
+}
 
-    // Deserialize the proof
+/// Reads and verifies the proof at `proof_path` against the trusted Notary keys, without printing it
+pub(crate) fn verify_proof_file(
+    proof_path: &str,
+    notary_pubkey_paths: &[PathBuf],
+) -> Result<VerifiedProof, Box<dyn std::error::Error>> {
     let proof = std::fs::read_to_string(proof_path)
         .map_err(|e| format!("Failed to read proof at {}: {}", proof_path, e))?;
-    let proof: TlsProof = serde_json::from_str(proof.as_str())
-        .map_err(|e| format!("Failed to parse proof: {}", e))?;
+
+    verify_proof_json(&proof, notary_pubkey_paths)
+}
+
+/// Verifies a proof serialized to JSON, as written to a proof file
+pub(crate) fn verify_proof_json(
+    proof: &str,
+    notary_pubkey_paths: &[PathBuf],
+) -> Result<VerifiedProof, Box<dyn std::error::Error>> {
+    let trusted_keys = trusted_notary_pubkeys(notary_pubkey_paths)?;
+    if notary_pubkey_paths.is_empty() {
+        // Kept off stdout, which may carry the proof itself
+        eprintln!("🚨 WARNING: Verifying a proof generated by a dummy notary.");
+        eprintln!("🚨 WARNING: Dummy notary is used for testing purposes only. It is not secure and should not be used in production.");
+    }
+
+    // Deserialize the proof
+    let proof: TlsProof =
+        serde_json::from_str(proof).map_err(|e| format!("Failed to parse proof: {}", e))?;
 
     let TlsProof {
         // The session proof establishes the identity of the server and the commitments
@@ -32,9 +189,23 @@ pub fn verify_attribution(proof_path: &str) -> Result<(), Box<dyn std::error::Er
     //
     // This verifies the identity of the server using a default certificate verifier which trusts
     // the root certificates from the `webpki-roots` crate.
-    session
-        .verify_with_default_cert_verifier(notary_pubkey()?)
-        .map_err(|e| format!("Failed to verify session proof: {}", e))?;
+    let mut verifying_key = None;
+    let mut failures = Vec::new();
+    for (key_id, key) in trusted_keys {
+        match session.verify_with_default_cert_verifier(key) {
+            Ok(()) => {
+                verifying_key = Some(key_id);
+                break;
+            }
+            Err(e) => failures.push(format!("`{}`: {}", key_id, e)),
+        }
+    }
+    let verifying_key = verifying_key.ok_or_else(|| {
+        format!(
+            "Failed to verify session proof against any of the trusted Notary keys:\n{}",
+            failures.join("\n")
+        )
+    })?;
 
     let SessionProof {
         // The session header that was signed by the Notary is a succinct commitment to the TLS transcript.
@@ -57,26 +228,410 @@ pub fn verify_attribution(proof_path: &str) -> Result<(), Box<dyn std::error::Er
     sent.set_redacted(b'X');
     recv.set_redacted(b'X');
 
-    println!("-------------------------------------------------------------------");
-    println!(
-        "Successfully verified that the bytes below came from a session with {:?} at {}.",
-        session_info.server_name, time
-    );
-    println!("Note that the bytes which the Prover chose not to disclose are shown as X.");
-    println!();
-    println!("Messages sent:");
-    println!();
-    println!("{}", String::from_utf8(sent.data().to_vec()).unwrap());
-    println!();
-    println!("Messages received:");
-    println!();
-    println!("{}", String::from_utf8(recv.data().to_vec()).unwrap());
-    println!("-------------------------------------------------------------------");
+    let mut sent_redaction = RedactionStats::of(&sent);
+    let mut recv_redaction = RedactionStats::of(&recv);
+
+    let sent = str::from_utf8(sent.data())
+        .map_err(|e| format!("Sent transcript is not valid UTF-8: {}", e))?;
+    // Chunk framing may split a character of the body, so the responses are parsed as bytes and
+    // the received transcript is only decoded for display
+    let recv_bytes = recv.data();
+    let recv = String::from_utf8_lossy(recv_bytes);
+
+    sent_redaction.redacted_headers = redacted_headers(sent);
+    recv_redaction.redacted_headers = redacted_headers(&recv);
+
+    // Check that the revealed requests were sent to the server the session was held with
+    let ServerName::Dns(server_name) = &session_info.server_name;
+    let requests = if sent.bytes().all(|b| b == b'X') {
+        // The Prover chose not to disclose the requests at all
+        None
+    } else {
+        let requests = parse_requests(sent)?;
+        for request in &requests {
+            if let Some(host) = &request.host {
+                if !host.eq_ignore_ascii_case(server_name) {
+                    Err(format!(
+                        "Request to `{}{}` was sent with the `Host: {}` header, but the session was held with `{}`",
+                        request.method, request.path, host, server_name
+                    ))?;
+                }
+            }
+        }
+        check_conversation_order(&requests)?;
+        // The replies can only be checked against the responses when those were disclosed too
+        if !recv_bytes.iter().all(|&b| b == b'X') {
+            check_replies(&requests, &parse_response_bodies(recv_bytes)?)?;
+        }
+        Some(requests)
+    };
+
+    // Reject stale proofs if the Prover committed to an expiry time
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| format!("Failed to read the current time: {}", e))?
+        .as_secs();
+    let expires_at = check_expiry(sent, now)?;
+
+    Ok(VerifiedProof {
+        server_name: server_name.clone(),
+        time,
+        verifying_key,
+        expires_at,
+        requests,
+        sent: sent.to_string(),
+        recv: recv.to_string(),
+        sent_redaction,
+        recv_redaction,
+    })
+}
+
+/// Parses the HTTP requests revealed in the sent transcript, which carries them back to back
+pub fn parse_requests(sent: &str) -> Result<Vec<RequestView>, String> {
+    let mut requests = Vec::new();
+    let mut rest = sent;
+
+    while !rest.is_empty() {
+        let (head, after_head) = rest
+            .split_once("\r\n\r\n")
+            .ok_or("Sent transcript contains an incomplete request head")?;
+        let mut lines = head.split("\r\n");
+
+        let request_line = lines.next().unwrap_or_default();
+        let mut parts = request_line.split(' ');
+        let (Some(method), Some(path), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(format!("Malformed request line `{request_line}`"));
+        };
+        if !version.starts_with("HTTP/") {
+            return Err(format!("Malformed request line `{request_line}`"));
+        }
+
+        let mut host = None;
+        let mut content_length = 0;
+        for line in lines {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("Malformed header `{line}`"))?;
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("host") {
+                host = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .parse::<usize>()
+                    .map_err(|e| format!("Failed to parse the content length `{value}`: {e}"))?;
+            }
+        }
+
+        let body = after_head
+            .get(..content_length)
+            .ok_or("Sent transcript contains an incomplete request body")?;
+        rest = &after_head[content_length..];
+
+        requests.push(RequestView {
+            method: method.to_string(),
+            path: path.to_string(),
+            host,
+            body: body.to_string(),
+        });
+    }
+
+    Ok(requests)
+}
+
+/// Checks that the chat requests form one ordered conversation, each resending the history of the
+/// previous request followed by the assistant's reply and a new user message.
+///
+/// The history may have been trimmed to a window, in which case the resent history must be the
+/// start of the previous request's messages, then its end along with the reply.
+pub fn check_conversation_order(requests: &[RequestView]) -> Result<(), String> {
+    let conversations = requests
+        .iter()
+        .filter_map(|request| {
+            let body = serde_json::from_str::<serde_json::Value>(&request.body).ok()?;
+            body.get("messages")?.as_array().cloned()
+        })
+        .collect::<Vec<_>>();
+
+    for (index, pair) in conversations.windows(2).enumerate() {
+        let (previous, current) = (&pair[0], &pair[1]);
+        let request_number = index + 2;
+
+        let [history @ .., message] = current.as_slice() else {
+            return Err(format!(
+                "Chat request #{request_number} contains no messages"
+            ));
+        };
+        if message["role"] != "user" {
+            return Err(format!(
+                "Chat request #{request_number} does not end with a user message"
+            ));
+        }
+
+        let continues_previous = (0..=history.len().min(previous.len())).any(|kept_start| {
+            if history[..kept_start] != previous[..kept_start] {
+                return false;
+            }
+            match &history[kept_start..] {
+                // Nothing but the start of the conversation was resent
+                [] => true,
+                [resent @ .., reply] => {
+                    reply["role"] == "assistant"
+                        && resent.len() <= previous.len() - kept_start
+                        && previous.ends_with(resent)
+                }
+            }
+        });
+        if !continues_previous {
+            return Err(format!(
+                "Chat request #{request_number} resends a history that differs from the previous request"
+            ));
+        }
+    }
 
     Ok(())
 }
 
-/// Returns a Notary pubkey trusted by this Verifier
+/// Checks that every assistant message resent in a chat request is a reply the model gave in an
+/// earlier exchange of the session, so an altered reply is caught. `response_bodies` holds the body
+/// of the response to each request, in order
+pub fn check_replies(requests: &[RequestView], response_bodies: &[String]) -> Result<(), String> {
+    let mut replies = Vec::new();
+    for (index, request) in requests.iter().enumerate() {
+        let messages = serde_json::from_str::<serde_json::Value>(&request.body)
+            .ok()
+            .and_then(|body| body.get("messages")?.as_array().cloned())
+            .unwrap_or_default();
+        for message in messages.iter().filter(|m| m["role"] == "assistant") {
+            if !replies
+                .iter()
+                .any(|reply: &String| message["content"] == reply.as_str())
+            {
+                return Err(format!(
+                    "Request #{} resends an assistant message that is not among the attested replies",
+                    index + 1
+                ));
+            }
+        }
+
+        if let Some(reply) = response_bodies.get(index).and_then(|body| reply_text(body)) {
+            replies.push(reply);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the text of the reply in a chat completion response body, as it is resent in the history
+fn reply_text(body: &str) -> Option<String> {
+    let body = serde_json::from_str::<serde_json::Value>(body).ok()?;
+    let message = body.get("choices")?.get(0)?.get("message")?;
+    match &message["content"] {
+        serde_json::Value::String(text) => Some(text.clone()),
+        // Only the text blocks are resent, as with a reply made only of tool calls
+        serde_json::Value::Array(parts) => Some(
+            parts
+                .iter()
+                .filter(|part| part["type"] == "text")
+                .filter_map(|part| part["text"].as_str())
+                .collect(),
+        ),
+        serde_json::Value::Null => Some(String::new()),
+        _ => None,
+    }
+}
+
+/// Parses the bodies of the HTTP responses revealed in the received transcript, which carries them
+/// back to back
+fn parse_response_bodies(recv: &[u8]) -> Result<Vec<String>, String> {
+    let mut bodies = Vec::new();
+    let mut rest = recv;
+
+    while !rest.is_empty() {
+        let (head, after_head) = split_once_bytes(rest, b"\r\n\r\n")
+            .ok_or("Received transcript contains an incomplete response head")?;
+        let head =
+            str::from_utf8(head).map_err(|e| format!("Response head is not valid UTF-8: {e}"))?;
+        let mut lines = head.split("\r\n");
+
+        let status_line = lines.next().unwrap_or_default();
+        if !status_line.starts_with("HTTP/") {
+            return Err(format!("Malformed status line `{status_line}`"));
+        }
+
+        let mut content_length = None;
+        let mut chunked = false;
+        for line in lines {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("Malformed header `{line}`"))?;
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length =
+                    Some(value.parse::<usize>().map_err(|e| {
+                        format!("Failed to parse the content length `{value}`: {e}")
+                    })?);
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.eq_ignore_ascii_case("chunked");
+            }
+        }
+
+        let (body, after_body) = if chunked {
+            read_chunked_body(after_head)?
+        } else {
+            // Without a length, the body runs until the connection is closed
+            let content_length = content_length.unwrap_or(after_head.len());
+            if after_head.len() < content_length {
+                return Err("Received transcript contains an incomplete response body".to_string());
+            }
+            let (body, after_body) = after_head.split_at(content_length);
+            (body.to_vec(), after_body)
+        };
+        let body = String::from_utf8(body)
+            .map_err(|e| format!("Response body is not valid UTF-8: {e}"))?;
+        bodies.push(body);
+        rest = after_body;
+    }
+
+    Ok(bodies)
+}
+
+/// Decodes a body sent with the chunked transfer encoding, returning it along with the rest of the
+/// transcript
+fn read_chunked_body(mut rest: &[u8]) -> Result<(Vec<u8>, &[u8]), String> {
+    let incomplete = || "Received transcript contains an incomplete chunked body".to_string();
+    let mut body = Vec::new();
+
+    loop {
+        let (size_line, after_size) = split_once_bytes(rest, b"\r\n").ok_or_else(incomplete)?;
+        let size_line = String::from_utf8_lossy(size_line);
+        // Chunk extensions may follow the size after a `;`
+        let size = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|e| format!("Failed to parse the chunk size `{size_line}`: {e}"))?;
+
+        if size == 0 {
+            // The last chunk is followed by optional trailers and an empty line
+            rest = match after_size.strip_prefix(b"\r\n") {
+                Some(after_body) => after_body,
+                None => {
+                    split_once_bytes(after_size, b"\r\n\r\n")
+                        .ok_or_else(incomplete)?
+                        .1
+                }
+            };
+            break;
+        }
+
+        // A chunk may end in the middle of a character, so the body is only decoded once complete
+        let chunk = after_size.get(..size).ok_or_else(incomplete)?;
+        body.extend_from_slice(chunk);
+        rest = after_size[size..]
+            .strip_prefix(b"\r\n")
+            .ok_or_else(incomplete)?;
+    }
+
+    Ok((body, rest))
+}
+
+/// Splits `bytes` around the first occurrence of `delimiter`
+fn split_once_bytes<'a>(bytes: &'a [u8], delimiter: &[u8]) -> Option<(&'a [u8], &'a [u8])> {
+    let position = bytes
+        .windows(delimiter.len())
+        .position(|window| window == delimiter)?;
+    Some((&bytes[..position], &bytes[position + delimiter.len()..]))
+}
+
+/// Returns the names of the headers in `transcript` whose values were fully redacted
+fn redacted_headers(transcript: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for line in transcript.lines() {
+        let Some((name, value)) = line.split_once(": ") else {
+            continue;
+        };
+        let name = name.to_ascii_lowercase();
+        if is_header_name(&name)
+            && !value.is_empty()
+            && value.bytes().all(|b| b == b'X')
+            && !names.contains(&name)
+        {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn is_header_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Returns the expiry time committed to in the revealed request headers, if any, and rejects the
+/// proof if it has passed by `now`
+fn check_expiry(sent: &str, now: u64) -> Result<Option<u64>, String> {
+    let expires_at = find_expiry(sent)?;
+    if let Some(expires_at) = expires_at {
+        if now > expires_at {
+            Err(format!(
+                "The proof expired at {} and is no longer valid.",
+                chrono::DateTime::UNIX_EPOCH + Duration::from_secs(expires_at)
+            ))?;
+        }
+    }
+
+    Ok(expires_at)
+}
+
+/// Returns the earliest expiry time committed to in the revealed request headers, if any
+fn find_expiry(sent: &str) -> Result<Option<u64>, String> {
+    let mut expires_at: Option<u64> = None;
+    for line in sent.lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        if !name.trim().eq_ignore_ascii_case(PROOF_EXPIRY_HEADER) {
+            continue;
+        }
+
+        let value = value.trim().parse::<u64>().map_err(|e| {
+            format!("Failed to parse the `{PROOF_EXPIRY_HEADER}` header `{value}`: {e}")
+        })?;
+        expires_at = Some(expires_at.map_or(value, |current| current.min(value)));
+    }
+
+    Ok(expires_at)
+}
+
+/// Returns the Notary pubkeys trusted by this Verifier, each labelled with where it was loaded from
+pub(crate) fn trusted_notary_pubkeys(
+    notary_pubkey_paths: &[PathBuf],
+) -> Result<Vec<(String, p256::PublicKey)>, String> {
+    if notary_pubkey_paths.is_empty() {
+        if !cfg!(feature = "dummy-notary") {
+            return Err("No trusted Notary public key provided. Use `--notary-pubkey` to specify the Notary keys to trust, or enable the dummy-notary feature.".to_string());
+        }
+
+        return Ok(vec![("bundled dummy notary".to_string(), notary_pubkey()?)]);
+    }
+
+    notary_pubkey_paths
+        .iter()
+        .map(|path| {
+            let pem_file = std::fs::read_to_string(path).map_err(|e| {
+                format!("Failed to read Notary pubkey at {}: {}", path.display(), e)
+            })?;
+            let key = p256::PublicKey::from_public_key_pem(&pem_file).map_err(|e| {
+                format!("Failed to parse Notary pubkey at {}: {}", path.display(), e)
+            })?;
+            Ok((path.display().to_string(), key))
+        })
+        .collect()
+}
+
+/// Returns the bundled dummy Notary pubkey
 fn notary_pubkey() -> Result<p256::PublicKey, String> {
     let pem_file = str::from_utf8(include_bytes!("../../tlsn/notary.pub"))
         .map_err(|e| format!("Failed to read Notary pubkey: {}", e))?;
@@ -84,3 +639,214 @@ fn notary_pubkey() -> Result<p256::PublicKey, String> {
     p256::PublicKey::from_public_key_pem(pem_file)
         .map_err(|e| format!("Failed to parse Notary pubkey: {}", e))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPIRING_REQUEST: &str = "POST /v1/chat/completions HTTP/1.1\r\nhost: api.example.com\r\nx-proof-expires-at: 1000\r\ncontent-length: 0\r\n\r\n";
+
+    #[test]
+    fn back_to_back_requests_are_split() {
+        let sent = "POST /v1/chat/completions HTTP/1.1\r\nHost: api.example.com\r\nContent-Length: 2\r\n\r\n{}\
+                    GET /v1/models HTTP/1.1\r\nhost: api.example.com\r\n\r\n";
+        assert_eq!(
+            parse_requests(sent),
+            Ok(vec![
+                RequestView {
+                    method: "POST".to_string(),
+                    path: "/v1/chat/completions".to_string(),
+                    host: Some("api.example.com".to_string()),
+                    body: "{}".to_string(),
+                },
+                RequestView {
+                    method: "GET".to_string(),
+                    path: "/v1/models".to_string(),
+                    host: Some("api.example.com".to_string()),
+                    body: String::new(),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn zero_length_body_is_empty() {
+        let sent = "POST /v1/completions HTTP/1.1\r\ncontent-length: 0\r\n\r\n";
+        let requests = parse_requests(sent).unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].host, None);
+        assert!(requests[0].body.is_empty());
+    }
+
+    #[test]
+    fn malformed_request_line_is_rejected() {
+        let sent = "POST /v1/chat/completions\r\nhost: api.example.com\r\n\r\n";
+        assert_eq!(
+            parse_requests(sent),
+            Err("Malformed request line `POST /v1/chat/completions`".to_string())
+        );
+    }
+
+    #[test]
+    fn incomplete_body_is_rejected() {
+        let sent = "POST /v1/completions HTTP/1.1\r\ncontent-length: 10\r\n\r\n{}";
+        assert!(parse_requests(sent).is_err());
+    }
+
+    fn bundled_notary_pubkey_path() -> PathBuf {
+        PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/tlsn/notary.pub"))
+    }
+
+    #[test]
+    fn corrupted_proof_is_rejected() {
+        let truncated = r#"{"session":{"header":{"encoder_seed":[1,2,3],"#;
+        let err = verify_proof_json(truncated, &[bundled_notary_pubkey_path()]).unwrap_err();
+        assert!(
+            err.to_string().starts_with("Failed to parse proof"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn unreadable_notary_pubkey_is_rejected() {
+        let missing = PathBuf::from("does/not/exist/notary.pub");
+        let err = trusted_notary_pubkeys(&[bundled_notary_pubkey_path(), missing]).unwrap_err();
+        assert!(err.starts_with("Failed to read Notary pubkey"), "{err}");
+    }
+
+    fn chat_request(messages: serde_json::Value) -> RequestView {
+        RequestView {
+            method: "POST".to_string(),
+            path: "/v1/chat/completions".to_string(),
+            host: Some("api.example.com".to_string()),
+            body: serde_json::json!({ "messages": messages }).to_string(),
+        }
+    }
+
+    fn chat_response(reply: &str) -> String {
+        serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": reply } }] })
+            .to_string()
+    }
+
+    /// Two rounds of a conversation, the second resending the reply to the first, and the responses
+    /// as received, the first with a length and the second chunked
+    fn two_rounds(resent_reply: &str) -> (Vec<RequestView>, String) {
+        let requests = vec![
+            chat_request(serde_json::json!([{ "role": "user", "content": "Hi" }])),
+            chat_request(serde_json::json!([
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": resent_reply },
+                { "role": "user", "content": "Bye" }
+            ])),
+        ];
+
+        let (first, second) = (chat_response("Hello"), chat_response("Goodbye"));
+        let (second_start, second_end) = second.split_at(10);
+        let recv = format!(
+            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{first}\
+             HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n\
+             {:x}\r\n{second_start}\r\n{:x}\r\n{second_end}\r\n0\r\n\r\n",
+            first.len(),
+            second_start.len(),
+            second_end.len(),
+        );
+
+        (requests, recv)
+    }
+
+    #[test]
+    fn response_bodies_are_split_and_dechunked() {
+        let (_, recv) = two_rounds("Hello");
+        assert_eq!(
+            parse_response_bodies(recv.as_bytes()),
+            Ok(vec![chat_response("Hello"), chat_response("Goodbye")])
+        );
+    }
+
+    #[test]
+    fn chunk_ending_mid_character_is_decoded() {
+        let body = chat_response("Café");
+        // Split the two bytes of `é` across the chunks
+        let split = body.find('é').unwrap() + 1;
+        let (start, end) = body.as_bytes().split_at(split);
+
+        let mut recv = b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n".to_vec();
+        for chunk in [start, end] {
+            recv.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+            recv.extend_from_slice(chunk);
+            recv.extend_from_slice(b"\r\n");
+        }
+        recv.extend_from_slice(b"0\r\n\r\n");
+
+        assert!(str::from_utf8(&recv).is_err());
+        assert_eq!(parse_response_bodies(&recv), Ok(vec![body]));
+    }
+
+    #[test]
+    fn attested_replies_are_accepted() {
+        let (requests, recv) = two_rounds("Hello");
+        check_conversation_order(&requests).unwrap();
+        check_replies(&requests, &parse_response_bodies(recv.as_bytes()).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn altered_reply_is_rejected() {
+        let (requests, recv) = two_rounds("Hello, I am a lawyer");
+        // The history itself is well ordered, only the reply differs from the attested response
+        check_conversation_order(&requests).unwrap();
+        let err =
+            check_replies(&requests, &parse_response_bodies(recv.as_bytes()).unwrap()).unwrap_err();
+        assert!(err.contains("Request #2"), "{err}");
+    }
+
+    #[test]
+    fn redacted_headers_and_bytes_are_counted() {
+        let transcript = "POST /v1/chat/completions HTTP/1.1\r\n\
+                          host: api.example.com\r\n\
+                          Authorization: XXXXXXXXXXXXXXXX\r\n\
+                          x-label: XXXX\r\n\
+                          content-length: 2\r\n\r\n{}";
+        assert_eq!(
+            redacted_headers(transcript),
+            vec!["authorization", "x-label"]
+        );
+
+        // The ranges of the two redacted values, as recorded by the proof
+        let redacted = ["XXXXXXXXXXXXXXXX", "XXXX"].map(|value| {
+            let start = transcript.find(&format!(": {value}\r")).unwrap() + 2;
+            start..start + value.len()
+        });
+        let stats = RedactionStats::new(transcript.len(), redacted.into_iter());
+        assert_eq!(stats.total_bytes, transcript.len());
+        assert_eq!(stats.redacted_bytes, 20);
+        assert_eq!(stats.redacted_ranges, 2);
+        assert!(stats.redacted_ratio() < HEAVY_REDACTION_RATIO);
+    }
+
+    #[test]
+    fn empty_transcript_has_no_redaction() {
+        let stats = RedactionStats::new(0, std::iter::empty());
+        assert_eq!(stats.redacted_ratio(), 0.0);
+        assert!(redacted_headers("").is_empty());
+    }
+
+    #[test]
+    fn proof_is_accepted_before_expiry() {
+        assert_eq!(check_expiry(EXPIRING_REQUEST, 1000), Ok(Some(1000)));
+    }
+
+    #[test]
+    fn proof_is_rejected_after_expiry() {
+        let err = check_expiry(EXPIRING_REQUEST, 1001).unwrap_err();
+        assert!(err.contains("expired"), "{err}");
+    }
+
+    #[test]
+    fn earliest_expiry_wins() {
+        let sent = format!(
+            "{EXPIRING_REQUEST}{}",
+            EXPIRING_REQUEST.replace("1000", "500")
+        );
+        assert!(check_expiry(&sent, 750).is_err());
+    }
+}